@@ -0,0 +1,119 @@
+use opvm::lexer::lexer::Lexer;
+use opvm::vm::program::Program;
+use opvm::vm::vm::Vm;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+const BASELINE_ENV: &str = "OPVM_BENCH_BASELINE";
+const SAVE_ENV: &str = "OPVM_BENCH_SAVE";
+const TOLERANCE: f64 = 1.2;
+
+const SCENARIOS: &[(&str, &str)] = &[
+    ("dispatch", "
+        push 100000
+        .loop
+            nop
+            nop
+            nop
+            loop @loop
+    "),
+    ("arithmetic", "
+        push 0
+        push 100000
+        .loop
+            swap
+            push 3
+            add
+            push 2
+            mul
+            push 7
+            mod
+            swap
+            loop @loop
+    "),
+    ("heap", "
+        alloc $a
+        alloc $b
+        push 0
+        store $a
+        push 100000
+        .loop
+            load $a
+            store $b
+            xchg $a $b
+            loop @loop
+    "),
+    ("calls", "
+        push 100000
+        .loop
+            call @func
+            loop @loop
+        hlt
+        .func
+            enter 1
+            push 1
+            local.set 0
+            leave
+            ret
+    "),
+    ("allocation", "
+        push 10000
+        .loop
+            arena_begin
+            alloc $a
+            alloc $b
+            alloc $c
+            arena_end
+            loop @loop
+    ")
+];
+
+fn main() {
+    let mut results: BTreeMap<String, f64> = BTreeMap::new();
+    for (name, source) in SCENARIOS {
+        let program = Lexer::new().process(source.to_string()).expect("scenario should parse");
+        let elapsed = measure(&program);
+        let nanos = elapsed.as_nanos() as f64;
+        println!("{:<12} {:>12.0} ns/run", name, nanos);
+        results.insert(name.to_string(), nanos);
+    }
+
+    if let Ok(path) = env::var(SAVE_ENV) {
+        let lines: Vec<String> = results.iter().map(|(name, nanos)| format!("{} {}", name, nanos)).collect();
+        fs::write(&path, lines.join("\n")).unwrap_or_else(|e| panic!("Unable to write {}: {}", path, e));
+    }
+
+    if let Ok(path) = env::var(BASELINE_ENV) {
+        let baseline = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e));
+        let mut regressed = false;
+        for line in baseline.lines() {
+            let (name, nanos) = match line.split_once(' ') {
+                Some((name, nanos)) => (name, nanos.parse::<f64>().unwrap_or(f64::MAX)),
+                None => continue
+            };
+            if let Some(current) = results.get(name) {
+                if *current > nanos * TOLERANCE {
+                    println!("{} regressed: {:.0} ns/run against a baseline of {:.0} ns/run", name, current, nanos);
+                    regressed = true;
+                }
+            }
+        }
+        if regressed {
+            process::exit(1);
+        }
+    }
+}
+
+// Takes the fastest of several runs so a noisy machine doesn't fail the gate.
+fn measure(program: &Program) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let mut vm = Vm::new(false);
+        let program = program.clone();
+        let start = Instant::now();
+        vm.execute(program).expect("scenario should run");
+        best = best.min(start.elapsed());
+    }
+    best
+}
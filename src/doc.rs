@@ -0,0 +1,57 @@
+use crate::vm::opcode::OpCode;
+
+pub fn instruction_reference() -> String {
+    let mut reference = String::from("# opvm instruction reference\n\n");
+    reference.push_str("| Opcode | Operands | Stack | Flags | Description | Example |\n");
+    reference.push_str("|--------|----------|-------|-------|-------------|---------|\n");
+    for opcode in OpCode::all() {
+        let name: &str = opcode.into();
+        let doc = opcode.doc();
+        reference.push_str(format!(
+            "| `{}` | {} | {} | {} | {} | {} |\n",
+            name,
+            code(doc.operands),
+            code(doc.stack),
+            doc.flags,
+            doc.description.replace('|', "\\|"),
+            doc.example.lines().map(code).collect::<Vec<String>>().join("<br>")
+        ).as_str());
+    }
+    reference
+}
+
+fn code(s: &str) -> String {
+    if s.is_empty() {
+        return String::default();
+    }
+    format!("`{}`", s.replace('|', "\\|"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::lexer::Lexer;
+
+    #[test]
+    fn can_document_every_opcode() {
+        let reference = instruction_reference();
+        for opcode in OpCode::all() {
+            let name: &str = opcode.into();
+            assert_eq!(OpCode::from(name), opcode);
+            assert!(reference.contains(format!("| `{}` |", name).as_str()));
+        }
+        assert!(reference.contains("| `or` |  | `a b -- a\\|b` | sets zero |"));
+        assert!(reference.contains("| `jz` | `@label` | `--` | reads zero |"));
+        assert!(reference.contains("| `push 6`<br>`push 7`<br>`mul` |"));
+    }
+
+    #[test]
+    fn can_lex_every_example() {
+        for opcode in OpCode::all() {
+            let name: &str = opcode.into();
+            let program = Lexer::new().process(opcode.doc().example.to_string()).unwrap();
+            assert!(program.instructions.iter().any(|i| i.opcode == opcode), "{} example doesn't use it", name);
+            assert!(program.instructions.iter().all(|i| i.opcode != OpCode::Igl), "{} example doesn't lex", name);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::vm::opcode::OpCode;
+
+pub const DIALECTS: &[&str] = &["opvm", "intel", "att", "arm"];
+
+const INTEL: &[(&str, &str)] = &[
+    ("imul", "mul"),
+    ("idiv", "div"),
+    ("sal", "shl"),
+    ("jnge", "jl"),
+    ("jnle", "jg"),
+    ("jnl", "jge"),
+    ("jng", "jle"),
+    ("jnae", "jb"),
+    ("jnbe", "ja"),
+    ("jc", "jb"),
+    ("jeq", "je")
+];
+
+const ARM: &[(&str, &str)] = &[
+    ("b", "jmp"),
+    ("br", "jmp"),
+    ("bl", "call"),
+    ("beq", "je"),
+    ("bne", "jne"),
+    ("blt", "jl"),
+    ("bgt", "jg"),
+    ("ble", "jle"),
+    ("bge", "jge"),
+    ("bhi", "ja"),
+    ("blo", "jb"),
+    ("mul", "mul"),
+    ("sdiv", "div"),
+    ("lsl", "shl"),
+    ("lsr", "shr"),
+    ("asr", "sar"),
+    ("orr", "or"),
+    ("mvn", "not")
+];
+
+// Maps a mnemonic from another assembler onto the opvm opcode it stands for. Real opvm mnemonics are never
+// rewritten, so enabling a dialect can't change the meaning of existing code.
+pub fn resolve(dialect: &str, mnemonic: &str) -> Option<&'static str> {
+    if OpCode::from(mnemonic) != OpCode::Igl {
+        return None;
+    }
+    match dialect {
+        "intel" => lookup(INTEL, mnemonic),
+        "arm" => lookup(ARM, mnemonic),
+        "att" => {
+            let base = mnemonic.strip_suffix(['b', 'w', 'l', 'q'])?;
+            match OpCode::from(base) {
+                OpCode::Igl => lookup(INTEL, base),
+                opcode => Some(opcode.into())
+            }
+        }
+        _ => None
+    }
+}
+
+fn lookup(table: &[(&str, &'static str)], mnemonic: &str) -> Option<&'static str> {
+    table.iter().find(|(alias, _)| *alias == mnemonic).map(|(_, opcode)| *opcode)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_resolve_aliases() {
+        assert_eq!(resolve("intel", "imul"), Some("mul"));
+        assert_eq!(resolve("att", "pushq"), Some("push"));
+        assert_eq!(resolve("att", "imull"), Some("mul"));
+        assert_eq!(resolve("arm", "beq"), Some("je"));
+        assert_eq!(resolve("arm", "bt"), None);
+        assert_eq!(resolve("opvm", "imul"), None);
+    }
+}
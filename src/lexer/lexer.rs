@@ -1,27 +1,37 @@
+use std::ops::Range;
 use nom::branch::alt;
 use nom::IResult;
 use nom::bytes::complete::*;
 use nom::multi::{separated_list0};
 use nom::sequence::{delimited, preceded, terminated};
-use nom::combinator::{eof, opt, peek};
+use nom::combinator::{eof, map, opt, peek};
+use crate::lexer::aliases;
 use crate::lexer::token::{Token, TokenType};
+use crate::vm::field::Field;
 use crate::vm::instruction::Instruction;
-use crate::vm::program::Program;
+use crate::vm::opcode::OpCode;
+use crate::vm::program::{LabelOffset, Proc, Program};
 
+#[derive(Default)]
 pub struct Lexer {
-
+    strict: bool
 }
 
 impl Lexer {
     pub fn new() -> Self {
-        Lexer{}
+        Lexer{ strict: false }
+    }
+
+    // Lexes every file as if it started with #pragma strict.
+    pub fn with_strict(strict: bool) -> Self {
+        Lexer{ strict }
     }
 
     pub fn process(&self, input: String) -> Option<Program> {
         let matched = handle_lines(input.as_str());
         match matched {
             Ok((_, v)) => {
-                return Some(self.build(v));
+                return self.build(v);
             }
             Err(e) => println!("{:?}", e)
         }
@@ -29,14 +39,51 @@ impl Lexer {
         None
     }
 
-    fn build(&self, tokens: Vec<Token>) -> Program {
+    fn build(&self, tokens: Vec<Token>) -> Option<Program> {
         let mut pc: usize = 0;
         let mut program = Program::new();
         let mut current_directive = String::default();
-        for token in tokens {
+        let mut current_proc: Option<Proc> = None;
+        let mut dialect = String::from("opvm");
+        let mut strict = self.strict;
+        let mut failed = false;
+        for (line, token) in hoist_sections(tokens) {
             match token.token_type {
                 TokenType::Directive => {
-                    current_directive = token.content.unwrap();
+                    let directive = token.content.unwrap();
+                    if directive == "init" {
+                        program.init.push(pc);
+                    } else if directive == "fini" {
+                        program.fini.push(pc);
+                    }
+                    if let Some(name) = directive.strip_prefix("proc ") {
+                        if let Some(open) = current_proc.take() {
+                            println!("Error: proc {} on line {} is missing endp.", open.name, line + 1);
+                        }
+                        let name = "@".to_owned() + name.trim();
+                        program.labels.insert(name.clone(), pc);
+                        current_proc = Some(Proc { name, start: pc, end: pc });
+                    } else if let Some(name) = directive.strip_prefix("syntax ") {
+                        if aliases::DIALECTS.contains(&name.trim()) {
+                            dialect = name.trim().to_string();
+                        } else {
+                            println!("Error: unknown syntax {} on line {}, expected one of {}.", name.trim(), line + 1, aliases::DIALECTS.join(", "));
+                        }
+                    } else if let Some(pragma) = directive.strip_prefix("pragma ") {
+                        match pragma.trim() {
+                            "strict" => strict = true,
+                            other => println!("Error: unknown pragma {} on line {}.", other, line + 1)
+                        }
+                    } else if let Some(name) = directive.strip_prefix("entry ") {
+                        program.entry = Some("@".to_owned() + name.trim());
+                    } else if directive == "endp" {
+                        match current_proc.take() {
+                            Some(proc) => program.procs.push(Proc { end: pc, ..proc }),
+                            None => println!("Error: endp on line {} without a matching proc.", line + 1)
+                        }
+                    } else {
+                        current_directive = directive;
+                    }
                 }
                 TokenType::Label => {
                     if current_directive == "data" {
@@ -56,8 +103,34 @@ impl Lexer {
                     let to_parse = token.content.unwrap();
                     let parsed = parse_words(&to_parse);
                     match parsed {
-                        Ok((_, v)) => {
-                            program.instructions.push(Instruction::new_from_words(v))
+                        Ok((_, mut v)) => {
+                            if let Some(opcode) = v.first().and_then(|m| aliases::resolve(&dialect, m)) {
+                                v[0] = opcode;
+                            }
+                            let relative = relative_offsets(&v);
+                            let mut instruction = Instruction::new_from_words(v);
+                            instruction.line = Some(line + 1);
+                            if strict {
+                                match apply_strict(&mut instruction, &to_parse) {
+                                    Ok(true) => program.strict_symbols.push(pc),
+                                    Ok(false) => {}
+                                    Err(word) => {
+                                        println!("Error: bare word {} on line {} must be quoted in strict mode.", word, line + 1);
+                                        failed = true;
+                                    }
+                                }
+                            }
+                            let targets = branch_targets(instruction.opcode, instruction.operand.len());
+                            for (i, offset) in relative.into_iter().filter(|(i, _)| targets.contains(i)) {
+                                let target = pc as i64 + offset;
+                                if target < 0 {
+                                    println!("Error: relative jump {} on line {} is outside of the program.", offset, line + 1);
+                                    failed = true;
+                                }
+                                *instruction.operand.get_mut(i).unwrap() = Field::from(target);
+                            }
+                            program.label_offsets.extend(find_label_offsets(&instruction, &to_parse, pc, targets));
+                            program.instructions.push(instruction)
                         }
                         Err(e) => println!("Error: {:?}", e)
                     }
@@ -68,8 +141,128 @@ impl Lexer {
             }
         }
 
-        program
+        if let Some(open) = current_proc {
+            println!("Error: proc {} is missing endp.", open.name);
+        }
+        if failed {
+            return None;
+        }
+        program.dedupe_constants();
+        Some(program)
+    }
+}
+
+fn is_section(directive: &str) -> bool {
+    !directive.starts_with("proc ") && directive != "endp" && !directive.starts_with("entry ") && !directive.starts_with("syntax ") && !directive.starts_with("pragma ")
+}
+
+fn synthetic(content: &str) -> Token {
+    Token { content: Some(content.to_string()), token_type: TokenType::Instruction }
+}
+
+// Moves #init and #fini sections after the rest of the program, behind a hlt, and ends each with a ret so the
+// vm can call them like functions.
+fn hoist_sections(tokens: Vec<Token>) -> Vec<(usize, Token)> {
+    let mut program: Vec<(usize, Token)> = Vec::new();
+    let mut hoisted: Vec<(usize, Token)> = Vec::new();
+    let mut in_section = false;
+    let mut last_line = 0;
+    for (line, token) in tokens.into_iter().enumerate() {
+        last_line = line;
+        if let (TokenType::Directive, Some(directive)) = (&token.token_type, &token.content) {
+            if is_section(directive) && in_section {
+                hoisted.push((line, synthetic("ret")));
+            }
+            if is_section(directive) {
+                in_section = directive == "init" || directive == "fini";
+            }
+        }
+        if in_section {
+            hoisted.push((line, token));
+        } else {
+            program.push((line, token));
+        }
+    }
+    if in_section {
+        hoisted.push((last_line, synthetic("ret")));
+    }
+    if !hoisted.is_empty() {
+        program.push((last_line, synthetic("hlt")));
+        program.extend(hoisted);
+    }
+    program
+}
+
+// Under #pragma strict a quoted operand is always a string, even when it looks like a number, and push no longer
+// accepts bare words as strings. Returns whether the instruction pushes an unquoted @symbol, which the vm then
+// refuses to push when it isn't defined, or the first bare word it found.
+fn apply_strict(instruction: &mut Instruction, source: &str) -> Result<bool, String> {
+    let quoted = match quoted_words(source) {
+        Ok((_, quoted)) => quoted,
+        Err(_) => return Ok(false)
+    };
+    let mut symbol = false;
+    for (i, is_quoted) in quoted.into_iter().skip(1).enumerate() {
+        let operand = match instruction.operand.get_mut(i) {
+            Some(operand) => operand,
+            None => break
+        };
+        if is_quoted {
+            *operand = Field::from(operand.to_string());
+        } else if let (OpCode::Push, Some(word)) = (instruction.opcode, operand.to_str()) {
+            if !word.starts_with('@') {
+                return Err(word.to_string());
+            }
+            symbol = true;
+        }
+    }
+    Ok(symbol)
+}
+
+// The operands a branch jumps to: every switch target, and the last operand of the others, which comes after the
+// variable in jnull and jnotnull.
+fn branch_targets(opcode: OpCode, operands: usize) -> Range<usize> {
+    match opcode {
+        OpCode::Switch => 0..operands,
+        op if op.is_branch() && operands > 0 => operands - 1..operands,
+        _ => 0..0
+    }
+}
+
+// Operands written as +n or -n, by operand index.
+fn relative_offsets(words: &[&str]) -> Vec<(usize, i64)> {
+    words.iter().skip(1).enumerate()
+        .filter(|(_, word)| word.starts_with('+') || word.starts_with('-'))
+        .filter_map(|(i, word)| word.parse::<i64>().ok().map(|offset| (i, offset)))
+        .collect()
+}
+
+fn find_label_offsets(instruction: &Instruction, source: &str, pc: usize, targets: Range<usize>) -> Vec<LabelOffset> {
+    if targets.is_empty() {
+        return vec![];
+    }
+    let quoted = match quoted_words(source) {
+        Ok((_, quoted)) => quoted,
+        Err(_) => return vec![]
+    };
+    let mut found = Vec::new();
+    for (i, operand) in instruction.operand.to_vec().iter().enumerate() {
+        if !targets.contains(&i) || quoted.get(i + 1) != Some(&false) {
+            continue;
+        }
+        let target = match operand.to_str() {
+            Some(s) if s.starts_with('@') => s,
+            _ => continue
+        };
+        let split = match target.rfind(['+', '-']) {
+            Some(i) if i > 0 => i,
+            _ => continue
+        };
+        if let Ok(offset) = target[split..].parse::<i64>() {
+            found.push(LabelOffset { pc, operand: i, label: target[..split].to_string(), offset });
+        }
     }
+    found
 }
 
 fn match_newline(i: &str) -> IResult<&str,&str> {
@@ -144,9 +337,12 @@ fn parse_words(i: &str) -> IResult<&str, Vec<&str>> {
     separated_list0(tag(" "), alt((match_quote, match_word)))(i)
 }
 
+fn quoted_words(i: &str) -> IResult<&str, Vec<bool>> {
+    separated_list0(tag(" "), alt((map(get_quoted, |_| true), map(alt((match_quote, match_word)), |_| false))))(i)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::vm::field::Field;
     use super::*;
 
     #[test]
@@ -177,7 +373,197 @@ mod test {
         assert!(instructions.is_some());
         let unwrapped = instructions.unwrap();
         assert_eq!(unwrapped.labels.len(), 1);
-        assert_eq!(*unwrapped.labels.get("@main").unwrap(), 0 as usize);
+        assert_eq!(*unwrapped.labels.get("@main").unwrap(), 0_usize);
+    }
+
+    #[test]
+    fn can_parse_procs() {
+        let assm = r#"
+        #code
+            call @greet
+            hlt
+        #proc greet
+            push "hi"
+            println
+            ret
+        #endp
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        assert!(instructions.is_some());
+        let unwrapped = instructions.unwrap();
+        assert_eq!(*unwrapped.labels.get("@greet").unwrap(), 2);
+        assert_eq!(unwrapped.procs, vec![Proc { name: "@greet".to_string(), start: 2, end: 5 }]);
+    }
+
+    #[test]
+    fn can_use_syntax_aliases() {
+        let assm = r#"
+        #syntax arm
+        #code
+            push 1
+            push 2
+            blt @less
+            bl @less
+        .less
+            hlt
+        #syntax att
+            pushq 3
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        let unwrapped = instructions.unwrap();
+        let opcodes: Vec<OpCode> = unwrapped.instructions.iter().map(|i| i.opcode).collect();
+        assert_eq!(opcodes, vec![OpCode::Push, OpCode::Push, OpCode::Jl, OpCode::Call, OpCode::Hlt, OpCode::Push]);
+    }
+
+    #[test]
+    fn can_use_strict_pragma() {
+        let assm = r#"
+        push "42"
+        #pragma strict
+        push "42"
+        push 42
+        push @user
+        push "@user"
+        "#;
+        let unwrapped = Lexer::new().process(assm.to_string()).unwrap();
+        let operands: Vec<Field> = unwrapped.instructions.iter().map(|i| i.operand.get(0).unwrap().clone()).collect();
+        assert!(matches!(operands[0], Field::I(42)));
+        assert!(matches!(operands[1], Field::S(_)));
+        assert!(matches!(operands[2], Field::I(42)));
+        assert_eq!(unwrapped.strict_symbols, vec![3]);
+
+        assert!(Lexer::new().process("#pragma strict\npush hello".to_string()).is_none());
+        assert!(Lexer::new().process("push hello".to_string()).is_some());
+        assert!(Lexer::with_strict(true).process("push hello".to_string()).is_none());
+    }
+
+    #[test]
+    fn can_parse_literal_suffixes() {
+        let assm = r#"
+        #data
+            .limit 10u
+        #code
+            push 10u
+            push -3i
+            push 10x
+        "#;
+        let unwrapped = Lexer::new().process(assm.to_string()).unwrap();
+        let operands: Vec<Field> = unwrapped.instructions.iter().map(|i| i.operand.get(0).unwrap().clone()).collect();
+        assert!(matches!(unwrapped.data.get("@limit"), Some(Field::U(10))));
+        assert!(matches!(operands[0], Field::U(10)));
+        assert!(matches!(operands[1], Field::I(-3)));
+        assert!(matches!(operands[2], Field::S(_)));
+    }
+
+    #[test]
+    fn can_parse_entry() {
+        let assm = r#"
+        #entry start
+        #code
+            .start
+            hlt
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        assert!(instructions.is_some());
+        assert_eq!(instructions.unwrap().entry, Some("@start".to_string()));
+    }
+
+    #[test]
+    fn can_hoist_init_and_fini() {
+        let assm = r#"
+        #init
+            push "init"
+        #code
+            push "main"
+        #fini
+            push "fini"
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        assert!(instructions.is_some());
+        let unwrapped = instructions.unwrap();
+        let assembled: Vec<String> = unwrapped.instructions.iter().map(|i| i.assemble()).collect();
+        assert_eq!(assembled, vec!["push main", "hlt", "push init", "ret", "push fini", "ret"]);
+        assert_eq!(unwrapped.init, vec![2]);
+        assert_eq!(unwrapped.fini, vec![4]);
+    }
+
+    #[test]
+    fn can_resolve_label_offsets() {
+        let assm = r#"
+        #code
+            .loop
+            push 1
+            push 2
+            jmp @loop+1
+            jmp @loop+5
+            jmp "@loop+1"
+            push @loop+1
+            call @greet+1
+        "#;
+        let mut unwrapped = Lexer::new().process(assm.to_string()).unwrap();
+        assert_eq!(unwrapped.label_offsets.len(), 3);
+
+        let mut lib = Program::new();
+        lib.instructions.push(Instruction::new(OpCode::Nop, vec![]));
+        lib.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        lib.labels.insert("@greet".to_string(), 0);
+        unwrapped.link(lib).unwrap();
+        unwrapped.resolve_label_offsets().unwrap();
+
+        assert_eq!(unwrapped.labels.len(), 2);
+        assert_eq!(unwrapped.instructions[2].operand.pop(), Some(Field::from(1)));
+        assert_eq!(unwrapped.instructions[3].operand.pop(), Some(Field::from(5)));
+        assert_eq!(unwrapped.instructions[4].operand.pop(), Some(Field::from("@loop+1")));
+        assert_eq!(unwrapped.instructions[5].operand.pop(), Some(Field::from("@loop+1")));
+        assert_eq!(unwrapped.instructions[6].operand.pop(), Some(Field::from(8)));
+
+        for bad in [".loop\njmp @loop-1", ".loop\njmp @loop+9", "jmp @missing+1"] {
+            let mut program = Lexer::new().process(bad.to_string()).unwrap();
+            assert!(program.resolve_label_offsets().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn can_resolve_numeric_jump_targets() {
+        let assm = r#"
+            push 1
+            jmp +2
+            jne -2
+            jmp 0
+            push -2
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        assert!(instructions.is_some());
+        let mut unwrapped = instructions.unwrap();
+        assert_eq!(unwrapped.instructions[1].operand.pop(), Some(Field::from(3)));
+        assert_eq!(unwrapped.instructions[2].operand.pop(), Some(Field::from(0)));
+        assert_eq!(unwrapped.instructions[3].operand.pop(), Some(Field::from(0)));
+        assert_eq!(unwrapped.instructions[4].operand.pop(), Some(Field::from(-2)));
+        assert!(Lexer::new().process("push 1\njmp -2".to_string()).is_none());
+    }
+
+    #[test]
+    fn can_resolve_targets_in_every_branch_form() {
+        let assm = r#"
+            .top
+            push 1
+            jnull $x +2
+            jnotnull $x -1
+            switch +1 -2 @top+1
+            jnull $x @top+3
+        "#;
+        let mut unwrapped = Lexer::new().process(assm.to_string()).unwrap();
+        assert_eq!(unwrapped.instructions[1].operand.to_vec(), &vec![Field::from("$x"), Field::from(3)]);
+        assert_eq!(unwrapped.instructions[2].operand.to_vec(), &vec![Field::from("$x"), Field::from(1)]);
+        assert_eq!(unwrapped.instructions[3].operand.to_vec(), &vec![Field::from(4), Field::from(1), Field::from("@top+1")]);
+        assert_eq!(unwrapped.label_offsets.iter().map(|l| (l.pc, l.operand)).collect::<Vec<_>>(), vec![(3, 2), (4, 1)]);
+
+        unwrapped.resolve_label_offsets().unwrap();
+        assert_eq!(unwrapped.instructions[3].operand.pop(), Some(Field::from(1)));
+        assert_eq!(unwrapped.instructions[4].operand.pop(), Some(Field::from(3)));
+
+        assert!(Lexer::new().process("switch +1 -1".to_string()).is_none());
+        assert!(Lexer::new().process("jnull $x -1".to_string()).is_none());
     }
 
     #[test]
@@ -202,6 +588,22 @@ mod test {
         assert_eq!(unwrapped.instructions.len(), 0);
     }
 
+    #[test]
+    fn can_track_source_lines() {
+        let assm = r#"
+        #code
+            push 1
+
+            ; comment
+            print
+        "#;
+        let instructions = Lexer::new().process(assm.to_string());
+        assert!(instructions.is_some());
+        let unwrapped = instructions.unwrap();
+        assert_eq!(unwrapped.instructions[0].line, Some(3));
+        assert_eq!(unwrapped.instructions[1].line, Some(6));
+    }
+
     #[test]
     fn can_have_comments_on_lines() {
         let assm = r#"
@@ -1,2 +1,4 @@
+pub mod aliases;
 pub mod token;
-pub mod lexer;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod lexer;
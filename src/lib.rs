@@ -0,0 +1,6 @@
+#![allow(clippy::result_large_err)]
+
+pub mod doc;
+pub mod lexer;
+pub mod manifest;
+pub mod vm;
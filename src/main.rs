@@ -1,25 +1,141 @@
-use crate::lexer::lexer::Lexer;
-use crate::vm::vm::Vm;
+use opvm::doc;
+use opvm::lexer::lexer::Lexer;
+use opvm::manifest::Manifest;
+use opvm::vm::program::Program;
+use opvm::vm::vm::Vm;
+use opvm::vm::options::{Capability, VmOptions};
+use std::io::{self, IsTerminal};
+use std::{env, fs, process};
+use std::path::Path;
 
-mod lexer;
-mod vm;
+#[derive(PartialEq)]
+enum ErrorFormat {
+    Text,
+    Json
+}
+
+#[derive(Default)]
+struct Flags {
+    verify: bool,
+    trap_fallthrough: bool,
+    trap_overflow: bool,
+    reflection: Option<bool>,
+    seed: Option<u64>,
+    stats: bool,
+    profile_output: Option<String>,
+    args: Vec<String>,
+    strict: bool,
+    allow: Vec<Capability>
+}
 
 fn main() {
-    let lexer = Lexer::new();
-    let val = lexer.process(r#"
-    push "What is your name? "
-    print
-    input
-    push "Hi, "
-    swap
-    concat
-    println
-    "#.to_string());
-
-    let mut vm = Vm::new(true);
-    let result = vm.execute(val.unwrap());
-    match result {
-        Err(e) => {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut error_format = ErrorFormat::Text;
+    let mut flags = Flags::default();
+    let mut positional: Vec<&str> = Vec::new();
+
+    let (args, program_args) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (&args[..], &[][..])
+    };
+    flags.args = program_args.to_vec();
+
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--error-format=text" => error_format = ErrorFormat::Text,
+            "--error-format=json" => error_format = ErrorFormat::Json,
+            "--verify" => flags.verify = true,
+            "--trap-fallthrough" => flags.trap_fallthrough = true,
+            "--trap-overflow" => flags.trap_overflow = true,
+            "--reflection" => flags.reflection = Some(true),
+            "--no-reflection" => flags.reflection = Some(false),
+            "--stats" => flags.stats = true,
+            "--strict" => flags.strict = true,
+            a if a.starts_with("--seed=") => match a["--seed=".len()..].parse::<u64>() {
+                Ok(seed) => flags.seed = Some(seed),
+                Err(_) => usage(&format!("Invalid seed: {}", a))
+            },
+            a if a.starts_with("--allow=") => for name in a["--allow=".len()..].split(',') {
+                match Capability::from_name(name) {
+                    Some(capability) => flags.allow.push(capability),
+                    None => usage(&format!("Unknown capability: {}", name))
+                }
+            },
+            a if a.starts_with("--profile-output=") => flags.profile_output = Some(a["--profile-output=".len()..].to_string()),
+            a if a.starts_with("--") => usage(&format!("Unknown option: {}", a)),
+            a => positional.push(a)
+        }
+    }
+
+    match positional.as_slice() {
+        ["run"] => run_project(error_format, flags),
+        ["vendor"] => vendor(),
+        ["doc"] => print!("{}", doc::instruction_reference()),
+        ["run", file] => run_file(file, error_format, flags),
+        _ => usage("Expected a command.")
+    }
+}
+
+fn run_file(file: &str, error_format: ErrorFormat, flags: Flags) {
+    let source = fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("Unable to read {}: {}", file, e)));
+
+    let mut program = match Lexer::with_strict(flags.strict).process(source) {
+        Some(p) => p,
+        None => process::exit(1)
+    };
+    program.resolve_label_offsets().unwrap_or_else(|e| fail(&e));
+
+    run(program, VmOptions::default(), error_format, flags);
+}
+
+fn run_project(error_format: ErrorFormat, flags: Flags) {
+    let dir = Path::new(".");
+    let manifest = Manifest::load(dir).unwrap_or_else(|e| fail(&e));
+    let program = manifest.program(dir, &Lexer::with_strict(flags.strict)).unwrap_or_else(|e| fail(&e));
+
+    run(program, manifest.options(), error_format, flags);
+}
+
+fn vendor() {
+    let dir = Path::new(".");
+    let manifest = Manifest::load(dir).unwrap_or_else(|e| fail(&e));
+    for name in manifest.vendor(dir).unwrap_or_else(|e| fail(&e)) {
+        println!("Vendored {}", name);
+    }
+}
+
+fn run(program: Program, mut options: VmOptions, error_format: ErrorFormat, flags: Flags) {
+    options.capabilities.extend(&flags.allow);
+    if flags.verify {
+        for warning in program.verify() {
+            eprintln!("{}", warning);
+        }
+    }
+    let mut vm = Vm::with_options(VmOptions {
+        error_color: error_format == ErrorFormat::Text && io::stdout().is_terminal(),
+        trap_fallthrough: flags.trap_fallthrough,
+        trap_on_overflow: flags.trap_overflow,
+        reflection: flags.reflection.unwrap_or_else(|| program.uses_reflection()),
+        seed: flags.seed.or(options.seed),
+        profile: flags.profile_output.is_some(),
+        args: flags.args.clone(),
+        ..options
+    });
+    let result = vm.execute(program);
+    if flags.stats {
+        eprintln!("{}", vm.stats());
+    }
+    if let Some(path) = &flags.profile_output {
+        fs::write(path, vm.profile().folded()).unwrap_or_else(|e| fail(&format!("Unable to write {}: {}", path, e)));
+    }
+    let e = match result {
+        Ok(status) if status.success() => return,
+        Ok(status) => process::exit(status.process_code()),
+        Err(e) => e
+    };
+    match error_format {
+        ErrorFormat::Json => eprintln!("{}", e.to_json()),
+        ErrorFormat::Text => {
             println!("Error: {}", e.message);
             println!("===== Stack Trace =====");
             for item in e.stacktrace {
@@ -29,7 +145,22 @@ fn main() {
             for item in e.app_stack {
                 println!("{}", item);
             }
-        },
-        Ok(_) => ()
+        }
     }
+    process::exit(1);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Usage: opvm run [file] [--error-format=text|json] [--verify] [--trap-fallthrough] [--trap-overflow]");
+    eprintln!("                          [--reflection|--no-reflection] [--seed=n] [--stats] [--strict]");
+    eprintln!("                          [--allow=capability,...] [--profile-output=file] [-- args...]");
+    eprintln!("       opvm vendor");
+    eprintln!("       opvm doc");
+    process::exit(2);
 }
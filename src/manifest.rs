@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::fs;
+use std::env;
+use std::path::{Path, PathBuf};
+use crate::lexer::lexer::Lexer;
+use crate::vm::field::Field;
+use crate::vm::options::{Capability, VmOptions};
+use crate::vm::program::Program;
+
+pub const MANIFEST_FILE: &str = "opvm.toml";
+pub const VENDOR_DIR: &str = "vendor";
+pub const REGISTRY_ENV: &str = "OPVM_REGISTRY";
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Array(Vec<Value>)
+}
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub entry: String,
+    pub include: Vec<String>,
+    pub libraries: Vec<String>,
+    pub registry: Option<String>,
+    pub constants: HashMap<String, Field>,
+    pub heap_size: Option<usize>,
+    pub capabilities: Option<Vec<Capability>>
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Manifest, String> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(s) => Manifest::parse(&s),
+            Err(e) => Err(format!("Unable to read {}: {}", path.display(), e))
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        let mut table = String::default();
+
+        for (i, raw) in input.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                table = line[1..line.len() - 1].trim().to_string();
+                if table != "constants" {
+                    return Err(format!("Unknown table [{}] on line {}.", table, i + 1));
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), parse_value(v.trim()).map_err(|e| format!("{} on line {}.", e, i + 1))?),
+                None => return Err(format!("Expected key = value on line {}.", i + 1))
+            };
+
+            if table == "constants" {
+                let field = match value {
+                    Value::Str(s) => Field::from(s),
+                    Value::Int(n) => Field::from(n),
+                    Value::Array(_) => return Err(format!("Constant {} cannot be an array on line {}.", key, i + 1))
+                };
+                manifest.constants.insert(key.to_string(), field);
+                continue;
+            }
+
+            match (key, value) {
+                ("entry", Value::Str(s)) => manifest.entry = s,
+                ("include", Value::Array(items)) => manifest.include = string_array(items, key, i)?,
+                ("libraries", Value::Array(items)) => manifest.libraries = string_array(items, key, i)?,
+                ("registry", Value::Str(s)) => manifest.registry = Some(s),
+                ("heap_size", Value::Int(n)) if n >= 0 => manifest.heap_size = Some(n as usize),
+                ("capabilities", Value::Array(items)) => {
+                    let mut capabilities = Vec::new();
+                    for name in string_array(items, key, i)? {
+                        match Capability::from_name(&name) {
+                            Some(c) => capabilities.push(c),
+                            None => return Err(format!("Unknown capability {} on line {}.", name, i + 1))
+                        }
+                    }
+                    manifest.capabilities = Some(capabilities);
+                }
+                (k, _) => return Err(format!("Invalid or unknown key {} on line {}.", k, i + 1))
+            }
+        }
+
+        if manifest.entry.is_empty() {
+            return Err("Manifest is missing an entry file.".to_string());
+        }
+        Ok(manifest)
+    }
+
+    // Links the project's files, lexed with the given lexer, and its libraries, which are always lexed as their own
+    // pragmas say.
+    pub fn program(&self, dir: &Path, lexer: &Lexer) -> Result<Program, String> {
+        let mut program = Program::new();
+        let mut linked: Vec<String> = Vec::new();
+        self.link_into(dir, lexer, &self.resolver(dir), &mut program, &mut linked)?;
+        program.resolve_label_offsets()?;
+        Ok(program)
+    }
+
+    pub fn vendor(&self, dir: &Path) -> Result<Vec<String>, String> {
+        let resolver = self.resolver(dir);
+        let registry = match &resolver.registry {
+            Some(r) => r.clone(),
+            None => return Err(format!("No registry configured, set registry in {} or {}.", MANIFEST_FILE, REGISTRY_ENV))
+        };
+
+        let mut pending = self.libraries.clone();
+        let mut vendored: Vec<String> = Vec::new();
+        while let Some(name) = pending.pop() {
+            if vendored.contains(&name) {
+                continue;
+            }
+            let source = registry.join(&name);
+            let manifest = Manifest::load(&source)?;
+            copy_dir(&source, &resolver.vendor.join(&name))?;
+            pending.extend(manifest.libraries);
+            vendored.push(name);
+        }
+        Ok(vendored)
+    }
+
+    fn resolver(&self, dir: &Path) -> Resolver {
+        let registry = match &self.registry {
+            Some(r) => Some(dir.join(r)),
+            None => env::var(REGISTRY_ENV).ok().map(PathBuf::from)
+        };
+        Resolver {
+            vendor: dir.join(VENDOR_DIR),
+            registry
+        }
+    }
+
+    fn link_into(&self, dir: &Path, lexer: &Lexer, resolver: &Resolver, program: &mut Program, linked: &mut Vec<String>) -> Result<(), String> {
+        program.link(lex_file(&dir.join(&self.entry), lexer)?)?;
+        for include in self.include.iter() {
+            program.link(lex_file(&dir.join(include), lexer)?)?;
+        }
+        for (name, value) in self.constants.iter() {
+            let key = "@".to_owned() + name;
+            if program.data.contains_key(&key) || program.labels.contains_key(&key) {
+                return Err(format!("Constant {} is already defined by the program.", name));
+            }
+            program.data.insert(key, value.clone());
+        }
+        for name in self.libraries.iter() {
+            if linked.contains(name) {
+                continue;
+            }
+            linked.push(name.clone());
+            let library_dir = resolver.find(name)?;
+            Manifest::load(&library_dir)?.link_into(&library_dir, &Lexer::new(), resolver, program, linked)?;
+        }
+        Ok(())
+    }
+
+    pub fn options(&self) -> VmOptions {
+        VmOptions {
+            heap_size: self.heap_size,
+            capabilities: self.capabilities.clone().unwrap_or_else(Capability::all),
+            ..Default::default()
+        }
+    }
+}
+
+struct Resolver {
+    vendor: PathBuf,
+    registry: Option<PathBuf>
+}
+
+impl Resolver {
+    fn find(&self, name: &str) -> Result<PathBuf, String> {
+        let vendored = self.vendor.join(name);
+        if vendored.join(MANIFEST_FILE).is_file() {
+            return Ok(vendored);
+        }
+        if let Some(registry) = &self.registry {
+            let registered = registry.join(name);
+            if registered.join(MANIFEST_FILE).is_file() {
+                return Ok(registered);
+            }
+        }
+        Err(format!("Unable to find library {} in {} or the registry.", name, VENDOR_DIR))
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Unable to create {}: {}", to.display(), e))?;
+    let entries = fs::read_dir(from).map_err(|e| format!("Unable to read {}: {}", from.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Unable to read {}: {}", from.display(), e))?.path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target).map_err(|e| format!("Unable to copy {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+fn lex_file(path: &Path, lexer: &Lexer) -> Result<Program, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    match lexer.process(source) {
+        Some(p) => Ok(p),
+        None => Err(format!("Unable to parse {}.", path.display()))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => ()
+        }
+    }
+    line
+}
+
+fn parse_value(input: &str) -> Result<Value, String> {
+    if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+        return Ok(Value::Str(input[1..input.len() - 1].to_string()));
+    }
+    if input.starts_with('[') && input.ends_with(']') {
+        let inner = input[1..input.len() - 1].trim();
+        let mut items = Vec::new();
+        for item in inner.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            items.push(parse_value(item)?);
+        }
+        return Ok(Value::Array(items));
+    }
+    match input.parse::<i64>() {
+        Ok(n) => Ok(Value::Int(n)),
+        Err(_) => Err(format!("Invalid value {}", input))
+    }
+}
+
+fn string_array(items: Vec<Value>, key: &str, line: usize) -> Result<Vec<String>, String> {
+    let mut strings = Vec::new();
+    for item in items {
+        match item {
+            Value::Str(s) => strings.push(s),
+            _ => return Err(format!("{} must only contain strings on line {}.", key, line + 1))
+        }
+    }
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_manifest() {
+        let manifest = Manifest::parse(r#"
+        # project manifest
+        entry = "src/main.asm"
+        include = ["lib/strings.asm", "lib/math.asm"]
+        libraries = ["std"]
+        registry = "../registry"
+        heap_size = 32 # variables
+        capabilities = []
+
+        [constants]
+        greeting = "Hello # there"
+        limit = 10
+        "#).unwrap();
+
+        assert_eq!(manifest.entry, "src/main.asm");
+        assert_eq!(manifest.include, vec!["lib/strings.asm", "lib/math.asm"]);
+        assert_eq!(manifest.libraries, vec!["std"]);
+        assert_eq!(manifest.registry, Some("../registry".to_string()));
+        assert_eq!(manifest.heap_size, Some(32));
+        assert_eq!(manifest.capabilities, Some(vec![]));
+        assert_eq!(*manifest.constants.get("greeting").unwrap(), Field::from("Hello # there"));
+        assert_eq!(*manifest.constants.get("limit").unwrap(), Field::from(10));
+    }
+
+    #[test]
+    fn cannot_parse_invalid_manifest() {
+        assert!(Manifest::parse("include = []").is_err());
+        assert!(Manifest::parse("entry = \"main.asm\"\nunknown = 1").is_err());
+        assert!(Manifest::parse("entry = \"main.asm\"\ncapabilities = [\"network\"]").is_err());
+        assert!(Manifest::parse("entry = \"main.asm\"\n[dependencies]").is_err());
+    }
+
+    #[test]
+    fn can_link_and_vendor_libraries() {
+        let root = env::temp_dir().join(format!("opvm-manifest-test-{}", std::process::id()));
+        let project = root.join("project");
+        let registry = root.join("registry");
+        let write = |path: PathBuf, contents: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(project.join(MANIFEST_FILE), "entry = \"main.asm\"\nlibraries = [\"greet\"]\nregistry = \"../registry\"");
+        write(project.join("main.asm"), "call @greet\ncall @greet+1\nhlt");
+        write(registry.join("greet").join(MANIFEST_FILE), "entry = \"greet.asm\"\nlibraries = [\"names\"]");
+        write(registry.join("greet").join("greet.asm"), ".greet\n    push @name\n    ret");
+        write(registry.join("names").join(MANIFEST_FILE), "entry = \"names.asm\"\n[constants]\nname = \"opvm\"");
+        write(registry.join("names").join("names.asm"), "");
+
+        let manifest = Manifest::load(&project).unwrap();
+        let program = manifest.program(&project, &Lexer::new()).unwrap();
+        assert_eq!(*program.labels.get("@greet").unwrap(), 3);
+        assert_eq!(program.instructions[1].operand.to_vec(), &vec![Field::from(4)]);
+        assert_eq!(*program.data.get("@name").unwrap(), Field::from("opvm"));
+
+        let mut vendored = manifest.vendor(&project).unwrap();
+        vendored.sort();
+        assert_eq!(vendored, vec!["greet", "names"]);
+        assert!(project.join(VENDOR_DIR).join("names").join("names.asm").is_file());
+
+        fs::remove_dir_all(&registry).unwrap();
+        assert!(manifest.program(&project, &Lexer::new()).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
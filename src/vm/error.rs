@@ -1,16 +1,140 @@
+use crate::vm::field::Field;
+use crate::vm::frame::Frame;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    StackUnderflow,
+    MissingOperand,
+    TypeMismatch,
+    OutOfRange,
+    UnknownLabel,
+    Heap,
+    Capability,
+    IllegalInstruction,
+    Fallthrough,
+    Overflow,
+    DivideByZero,
+    Cancelled,
+    Assertion,
+    Io
+}
+
+impl From<ErrorKind> for &str {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::StackUnderflow => "stack_underflow",
+            ErrorKind::MissingOperand => "missing_operand",
+            ErrorKind::TypeMismatch => "type_mismatch",
+            ErrorKind::OutOfRange => "out_of_range",
+            ErrorKind::UnknownLabel => "unknown_label",
+            ErrorKind::Heap => "heap",
+            ErrorKind::Capability => "capability",
+            ErrorKind::IllegalInstruction => "illegal_instruction",
+            ErrorKind::Fallthrough => "fallthrough",
+            ErrorKind::Overflow => "overflow",
+            ErrorKind::DivideByZero => "divide_by_zero",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Assertion => "assertion",
+            ErrorKind::Io => "io"
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
+    pub kind: ErrorKind,
     pub message: String,
+    pub pc: usize,
+    pub line: Option<usize>,
+    pub frames: Vec<Frame>,
+    pub stack: Vec<Field>,
     pub stacktrace: Vec<String>,
     pub app_stack: Vec<String>
 }
 
 impl Error {
-    pub fn new(message: String, stack: Vec<String>, app_stack: Vec<String>) -> Self {
+    pub fn new(kind: ErrorKind, message: String, stack: Vec<String>, app_stack: Vec<String>) -> Self {
         Error {
+            kind,
             message,
+            pc: 0,
+            line: None,
+            frames: vec![],
+            stack: vec![],
             stacktrace: stack,
             app_stack
         }
     }
-}
\ No newline at end of file
+
+    pub fn to_json(&self) -> String {
+        let kind: &str = self.kind.into();
+        let line = match self.line {
+            Some(l) => l.to_string(),
+            None => "null".to_string()
+        };
+        let frames: Vec<String> = self.frames.iter().map(|f| {
+            let frame_pointer = match f.frame_pointer {
+                Some(fp) => fp.to_string(),
+                None => "null".to_string()
+            };
+            format!("{{\"label\":{},\"return_pc\":{},\"stack_base\":{},\"frame_pointer\":{},\"locals\":{}}}",
+                    json_string(&f.label), f.return_pc, f.stack_base, frame_pointer, f.locals)
+        }).collect();
+        let stack: Vec<String> = self.stack.iter().map(json_field).collect();
+        let stacktrace: Vec<String> = self.stacktrace.iter().map(|s| json_string(s)).collect();
+
+        format!(
+            "{{\"kind\":\"{}\",\"message\":{},\"pc\":{},\"line\":{},\"frames\":[{}],\"stack\":[{}],\"stacktrace\":[{}]}}",
+            kind,
+            json_string(&self.message),
+            self.pc,
+            line,
+            frames.join(","),
+            stack.join(","),
+            stacktrace.join(",")
+        )
+    }
+}
+
+fn json_field(field: &Field) -> String {
+    match field {
+        Field::I(i) => i.to_string(),
+        Field::U(u) => u.to_string(),
+        Field::S(s) => json_string(s)
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let mut error = Error::new(ErrorKind::StackUnderflow, "Cannot pop \"empty\" stack.".to_string(), vec!["0\t | pop".to_string()], vec![]);
+        error.line = Some(3);
+        error.frames.push(Frame::new("@func".to_string(), 4, 1));
+        error.stack.push(Field::from(5));
+        error.stack.push(Field::from("hi"));
+
+        assert_eq!(error.to_json(), "{\"kind\":\"stack_underflow\",\"message\":\"Cannot pop \\\"empty\\\" stack.\",\"pc\":0,\"line\":3,\
+            \"frames\":[{\"label\":\"@func\",\"return_pc\":4,\"stack_base\":1,\"frame_pointer\":null,\"locals\":0}],\"stack\":[5,\"hi\"],\"stacktrace\":[\"0\\t | pop\"]}");
+    }
+}
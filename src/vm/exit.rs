@@ -0,0 +1,36 @@
+use crate::vm::field::Field;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExitStatus {
+    pub code: i64,
+    pub result: Option<Field>
+}
+
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+
+    // The code to exit the process with. Processes only report the low byte, so a failure outside 1..=255 is
+    // clamped into that range rather than being truncated, possibly to 0.
+    pub fn process_code(&self) -> i32 {
+        if self.success() {
+            return 0;
+        }
+        self.code.clamp(1, 255) as i32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_clamp_process_codes() {
+        let status = |code: i64| ExitStatus { code, result: None };
+        assert_eq!(status(0).process_code(), 0);
+        assert_eq!(status(3).process_code(), 3);
+        assert_eq!(status(256).process_code(), 255);
+        assert_eq!(status(-1).process_code(), 1);
+    }
+}
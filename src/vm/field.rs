@@ -1,42 +1,81 @@
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
+use std::rc::Rc;
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
 pub enum Field {
     I(i64),
     U(usize),
-    S(String)
+    S(Rc<str>)
 }
 
 impl Field {
     pub fn to_i(&self) -> Option<i64> {
         match self {
-            &Field::I(num) => Some(num),
+            Field::I(num) => Some(*num),
             _ => None
         }
     }
 
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Field::I(_) => "int",
+            Field::U(_) => "uint",
+            Field::S(_) => "str"
+        }
+    }
+
     pub fn to_str(&self) -> Option<&str> {
         match self {
-            &Field::S(ref s) => Some(s),
+            Field::S(s) => Some(s),
             _ => None
         }
     }
 
     pub fn to_s(&self) -> Option<String> {
         match self {
-            &Field::S(ref s) => Some(s.to_string()),
+            Field::S(s) => Some(s.to_string()),
             _ => None
         }
     }
 
+    pub fn unsigned_cmp(&self, other: &Field) -> Option<Ordering> {
+        let bits = |field: &Field| match field {
+            Field::I(i) => Some(*i as u64),
+            Field::U(u) => Some(*u as u64),
+            Field::S(_) => None
+        };
+        bits(self)?.partial_cmp(&bits(other)?)
+    }
+
     pub fn to_u(&self) -> Option<usize> {
         match self {
-            &Field::U(u) => Some(u),
+            Field::U(u) => Some(*u),
             _ => None
         }
     }
 }
 
+// Ints and uints compare by numeric value, strings compare with strings, and anything else is unordered.
+impl PartialOrd for Field {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Field::I(a), Field::I(b)) => a.partial_cmp(b),
+            (Field::U(a), Field::U(b)) => a.partial_cmp(b),
+            (Field::I(a), Field::U(b)) => (*a as i128).partial_cmp(&(*b as i128)),
+            (Field::U(a), Field::I(b)) => (*a as i128).partial_cmp(&(*b as i128)),
+            (Field::S(a), Field::S(b)) => a.partial_cmp(b),
+            _ => None
+        }
+    }
+}
+
+impl PartialEq for Field {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
 impl From<usize> for Field {
     fn from(u: usize) -> Self {
         Field::U(u)
@@ -57,22 +96,28 @@ impl From<i32> for Field {
 
 impl From<String> for Field {
     fn from(s: String) -> Self {
-        Field::S(s)
+        Field::S(Rc::from(s))
     }
 }
 
 impl From<&str> for Field {
     fn from(s: &str) -> Self {
-        Field::S(s.to_string())
+        Field::S(Rc::from(s))
+    }
+}
+
+impl From<Rc<str>> for Field {
+    fn from(s: Rc<str>) -> Self {
+        Field::S(s)
     }
 }
 
 impl Display for Field {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            &Field::I(i) => write!(f, "{}", i),
-            &Field::U(u) => write!(f, "{}", u),
-            &Field::S(ref s) => {
+            Field::I(i) => write!(f, "{}", i),
+            Field::U(u) => write!(f, "{}", u),
+            Field::S(s) => {
                 write!(f, "{}", s)
             },
         }
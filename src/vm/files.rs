@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+
+enum OpenFile {
+    Reader(File),
+    Writer(File)
+}
+
+// Files opened by a program, keyed by the handle returned from file_open.
+#[derive(Default)]
+pub struct FileTable {
+    files: HashMap<usize, OpenFile>,
+    next: usize
+}
+
+impl FileTable {
+    pub fn open(&mut self, path: &str, mode: &str) -> Result<usize, String> {
+        let file = match mode {
+            "r" => File::open(path).map(OpenFile::Reader),
+            "w" => File::create(path).map(OpenFile::Writer),
+            "a" => OpenOptions::new().append(true).create(true).open(path).map(OpenFile::Writer),
+            _ => return Err(format!("Unknown file mode {}, expected r, w or a!", mode))
+        }.map_err(|e| format!("Unable to open {}: {}", path, e))?;
+
+        let handle = self.next;
+        self.next += 1;
+        self.files.insert(handle, file);
+        Ok(handle)
+    }
+
+    pub fn read(&mut self, handle: usize) -> Result<String, String> {
+        match self.files.get_mut(&handle) {
+            Some(OpenFile::Reader(file)) => {
+                let mut contents = String::new();
+                file.read_to_string(&mut contents).map_err(|e| format!("Unable to read file {}: {}", handle, e))?;
+                Ok(contents)
+            }
+            Some(OpenFile::Writer(_)) => Err(format!("File {} was not opened for reading!", handle)),
+            None => Err(format!("File {} is not open!", handle))
+        }
+    }
+
+    pub fn write(&mut self, handle: usize, text: &str) -> Result<(), String> {
+        match self.files.get_mut(&handle) {
+            Some(OpenFile::Writer(file)) => file.write_all(text.as_bytes()).map_err(|e| format!("Unable to write file {}: {}", handle, e)),
+            Some(OpenFile::Reader(_)) => Err(format!("File {} was not opened for writing!", handle)),
+            None => Err(format!("File {} is not open!", handle))
+        }
+    }
+
+    pub fn close(&mut self, handle: usize) -> Result<(), String> {
+        match self.files.remove(&handle) {
+            Some(_) => Ok(()),
+            None => Err(format!("File {} is not open!", handle))
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
@@ -0,0 +1,27 @@
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub label: String,
+    pub return_pc: usize,
+    // The call instruction that pushed the frame, or None for a section or routine the vm entered itself.
+    pub call_pc: Option<usize>,
+    pub stack_base: usize,
+    pub frame_pointer: Option<usize>,
+    pub locals: usize
+}
+
+impl Frame {
+    pub fn new(label: String, return_pc: usize, stack_base: usize) -> Self {
+        Frame {
+            label,
+            return_pc,
+            call_pc: return_pc.checked_sub(1),
+            stack_base,
+            frame_pointer: None,
+            locals: 0
+        }
+    }
+
+    pub fn entered(label: String, return_pc: usize, stack_base: usize) -> Self {
+        Frame { call_pc: None, ..Frame::new(label, return_pc, stack_base) }
+    }
+}
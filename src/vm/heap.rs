@@ -1,4 +1,5 @@
 use crate::vm::field::Field;
+use std::mem;
 
 pub struct Heap {
     pub item: Option<Box<Field>>
@@ -10,4 +11,16 @@ impl Heap {
             item: None
         }
     }
-}
\ No newline at end of file
+
+    // An estimate of what the variable costs: its name, the entry itself and any boxed value.
+    pub fn size(&self, name: &str) -> usize {
+        let item = match &self.item {
+            Some(field) => match field.as_ref() {
+                Field::S(s) => mem::size_of::<Field>() + s.len(),
+                _ => mem::size_of::<Field>()
+            },
+            None => 0
+        };
+        name.len() + mem::size_of::<Heap>() + item
+    }
+}
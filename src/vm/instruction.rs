@@ -6,7 +6,8 @@ use crate::vm::stack::Stack;
 #[derive(Clone, Debug)]
 pub struct Instruction {
     pub opcode: opcode::OpCode,
-    pub operand: Stack<Field>
+    pub operand: Stack<Field>,
+    pub line: Option<usize>
 }
 
 impl Instruction {
@@ -18,36 +19,45 @@ impl Instruction {
 
         Instruction {
             opcode,
-            operand: stack
+            operand: stack,
+            line: None
         }
     }
 
     pub fn new_from_words(str: Vec<&str>) -> Self {
-        let pre_opcode = *str.get(0).unwrap();
+        let pre_opcode = *str.first().unwrap();
         let opcode = OpCode::from(pre_opcode);
         if opcode == OpCode::Igl {
             println!("Error: Unknown opcode: {:?}", str);
         }
         let mut stack: Stack<Field> = Stack::new();
-        for i in 1..str.len() {
-            stack.push(Instruction::construct_field(str[i]));
+        for word in str.iter().skip(1) {
+            stack.push(Instruction::construct_field(word));
         }
 
         Instruction {
             opcode,
-            operand: stack
+            operand: stack,
+            line: None
         }
     }
 
     pub fn construct_field(str: &str) -> Field {
-        match str.parse::<i64>() {
-            Ok(i) => { return Field::from(i); }
-            Err(_) => (),
+        if let Ok(i) = str.parse::<i64>() {
+            return Field::from(i);
         }
 
-        match str.parse::<i32>() {
-            Ok(i) => { return Field::from(i); }
-            Err(_) => (),
+        if let Ok(i) = str.parse::<i32>() {
+            return Field::from(i);
+        }
+
+        // An explicit suffix picks the integer type, e.g. 10u for a uint and 10i for an int.
+        if let Some(Ok(u)) = str.strip_suffix('u').map(|s| s.parse::<usize>()) {
+            return Field::from(u);
+        }
+
+        if let Some(Ok(i)) = str.strip_suffix('i').map(|s| s.parse::<i64>()) {
+            return Field::from(i);
         }
 
         Field::from(str)
@@ -59,10 +69,8 @@ impl Instruction {
         let mut final_string = String::default();
         final_string.push_str(str);
         let cloned_operands = self.operand.clone();
-        let operands = cloned_operands.to_vec();
-        for i in 0..operands.len() {
-            let item = &operands[i];
-            final_string.push_str(" ");
+        for item in cloned_operands.to_vec() {
+            final_string.push(' ');
             final_string.push_str(item.to_string().as_str());
         }
         final_string
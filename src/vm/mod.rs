@@ -1,9 +1,23 @@
+pub mod cancel;
+pub mod error;
+pub mod exit;
 pub mod field;
+pub mod frame;
 pub mod instruction;
+pub mod opcode;
+pub mod options;
+pub mod pool;
+pub mod profile;
 pub mod program;
+pub mod snapshot;
+pub mod stats;
+pub mod streams;
+pub mod telemetry;
+#[allow(clippy::module_inception)]
 pub mod vm;
 
-mod opcode;
 mod stack;
-mod error;
-mod heap;
\ No newline at end of file
+mod heap;
+mod rng;
+mod diff;
+mod files;
@@ -1,5 +1,16 @@
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum OpCode {
+// Declares OpCode along with the list of its variants, so OpCode::all() can't fall out of step with the enum.
+macro_rules! opcodes {
+    ($($variant:ident),*) => {
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        pub enum OpCode {
+            $($variant),*
+        }
+
+        const VARIANTS: &[OpCode] = &[$(OpCode::$variant),*];
+    }
+}
+
+opcodes! {
     Push,
     Pop,
     Add,
@@ -30,7 +41,71 @@ pub enum OpCode {
     Alloc,
     Free,
     Load,
-    Store
+    Store,
+    Abs,
+    Sign,
+    Adds,
+    Subs,
+    Popcnt,
+    Clz,
+    Ctz,
+    Bt,
+    Bts,
+    Btr,
+    Btc,
+    Arg,
+    ArenaBegin,
+    ArenaEnd,
+    Shl,
+    Shr,
+    Sar,
+    And,
+    Or,
+    Not,
+    Neg,
+    Cmp,
+    Jz,
+    Jnz,
+    Loop,
+    Xchg,
+    Cast,
+    Enter,
+    Leave,
+    LocalGet,
+    LocalSet,
+    Switch,
+    TailCall,
+    Ja,
+    Jb,
+    Muls,
+    Jnull,
+    Jnotnull,
+    Pow,
+    Sqrt,
+    Rand,
+    Seed,
+    Strlen,
+    Substr,
+    CharAt,
+    IndexOf,
+    Upper,
+    Lower,
+    Trim,
+    Replace,
+    Split,
+    Join,
+    Format,
+    Assert,
+    FileOpen,
+    FileRead,
+    FileWrite,
+    FileClose,
+    Env,
+    Args,
+    Cwd,
+    Sleep,
+    HeapMark,
+    HeapDiff
 }
 
 impl From<&str> for OpCode {
@@ -66,6 +141,70 @@ impl From<&str> for OpCode {
             "free" => OpCode::Free,
             "load" => OpCode::Load,
             "store" => OpCode::Store,
+            "abs" => OpCode::Abs,
+            "sign" => OpCode::Sign,
+            "adds" => OpCode::Adds,
+            "subs" => OpCode::Subs,
+            "popcnt" => OpCode::Popcnt,
+            "clz" => OpCode::Clz,
+            "ctz" => OpCode::Ctz,
+            "bt" => OpCode::Bt,
+            "bts" => OpCode::Bts,
+            "btr" => OpCode::Btr,
+            "btc" => OpCode::Btc,
+            "arg" => OpCode::Arg,
+            "arena_begin" => OpCode::ArenaBegin,
+            "arena_end" => OpCode::ArenaEnd,
+            "shl" => OpCode::Shl,
+            "shr" => OpCode::Shr,
+            "sar" => OpCode::Sar,
+            "and" => OpCode::And,
+            "or" => OpCode::Or,
+            "not" => OpCode::Not,
+            "neg" => OpCode::Neg,
+            "cmp" => OpCode::Cmp,
+            "jz" => OpCode::Jz,
+            "jnz" => OpCode::Jnz,
+            "loop" => OpCode::Loop,
+            "xchg" => OpCode::Xchg,
+            "cast" => OpCode::Cast,
+            "enter" => OpCode::Enter,
+            "leave" => OpCode::Leave,
+            "local.get" => OpCode::LocalGet,
+            "local.set" => OpCode::LocalSet,
+            "switch" => OpCode::Switch,
+            "tailcall" => OpCode::TailCall,
+            "ja" => OpCode::Ja,
+            "jb" => OpCode::Jb,
+            "muls" => OpCode::Muls,
+            "jnull" => OpCode::Jnull,
+            "jnotnull" => OpCode::Jnotnull,
+            "pow" => OpCode::Pow,
+            "sqrt" => OpCode::Sqrt,
+            "rand" => OpCode::Rand,
+            "seed" => OpCode::Seed,
+            "strlen" => OpCode::Strlen,
+            "substr" => OpCode::Substr,
+            "charat" => OpCode::CharAt,
+            "indexof" => OpCode::IndexOf,
+            "upper" => OpCode::Upper,
+            "lower" => OpCode::Lower,
+            "trim" => OpCode::Trim,
+            "replace" => OpCode::Replace,
+            "split" => OpCode::Split,
+            "join" => OpCode::Join,
+            "format" => OpCode::Format,
+            "assert" => OpCode::Assert,
+            "file_open" => OpCode::FileOpen,
+            "file_read" => OpCode::FileRead,
+            "file_write" => OpCode::FileWrite,
+            "file_close" => OpCode::FileClose,
+            "env" => OpCode::Env,
+            "args" => OpCode::Args,
+            "cwd" => OpCode::Cwd,
+            "sleep" => OpCode::Sleep,
+            "heap_mark" => OpCode::HeapMark,
+            "heap_diff" => OpCode::HeapDiff,
             _ => OpCode::Igl
         }
     }
@@ -104,7 +243,314 @@ impl From<OpCode> for &str {
             OpCode::Alloc => "alloc",
             OpCode::Free => "free",
             OpCode::Load => "load",
-            OpCode::Store => "store"
+            OpCode::Store => "store",
+            OpCode::Abs => "abs",
+            OpCode::Sign => "sign",
+            OpCode::Adds => "adds",
+            OpCode::Subs => "subs",
+            OpCode::Popcnt => "popcnt",
+            OpCode::Clz => "clz",
+            OpCode::Ctz => "ctz",
+            OpCode::Bt => "bt",
+            OpCode::Bts => "bts",
+            OpCode::Btr => "btr",
+            OpCode::Btc => "btc",
+            OpCode::Arg => "arg",
+            OpCode::ArenaBegin => "arena_begin",
+            OpCode::ArenaEnd => "arena_end",
+            OpCode::Shl => "shl",
+            OpCode::Shr => "shr",
+            OpCode::Sar => "sar",
+            OpCode::And => "and",
+            OpCode::Or => "or",
+            OpCode::Not => "not",
+            OpCode::Neg => "neg",
+            OpCode::Cmp => "cmp",
+            OpCode::Jz => "jz",
+            OpCode::Jnz => "jnz",
+            OpCode::Loop => "loop",
+            OpCode::Xchg => "xchg",
+            OpCode::Cast => "cast",
+            OpCode::Enter => "enter",
+            OpCode::Leave => "leave",
+            OpCode::LocalGet => "local.get",
+            OpCode::LocalSet => "local.set",
+            OpCode::Switch => "switch",
+            OpCode::TailCall => "tailcall",
+            OpCode::Ja => "ja",
+            OpCode::Jb => "jb",
+            OpCode::Muls => "muls",
+            OpCode::Jnull => "jnull",
+            OpCode::Jnotnull => "jnotnull",
+            OpCode::Pow => "pow",
+            OpCode::Sqrt => "sqrt",
+            OpCode::Rand => "rand",
+            OpCode::Seed => "seed",
+            OpCode::Strlen => "strlen",
+            OpCode::Substr => "substr",
+            OpCode::CharAt => "charat",
+            OpCode::IndexOf => "indexof",
+            OpCode::Upper => "upper",
+            OpCode::Lower => "lower",
+            OpCode::Trim => "trim",
+            OpCode::Replace => "replace",
+            OpCode::Split => "split",
+            OpCode::Join => "join",
+            OpCode::Format => "format",
+            OpCode::Assert => "assert",
+            OpCode::FileOpen => "file_open",
+            OpCode::FileRead => "file_read",
+            OpCode::FileWrite => "file_write",
+            OpCode::FileClose => "file_close",
+            OpCode::Env => "env",
+            OpCode::Args => "args",
+            OpCode::Cwd => "cwd",
+            OpCode::Sleep => "sleep",
+            OpCode::HeapMark => "heap_mark",
+            OpCode::HeapDiff => "heap_diff"
+        }
+    }
+}
+
+pub struct OpCodeDoc {
+    pub operands: &'static str,
+    pub stack: &'static str,
+    pub flags: &'static str,
+    pub description: &'static str,
+    pub example: &'static str
+}
+
+impl OpCode {
+    pub fn all() -> Vec<OpCode> {
+        VARIANTS.iter().copied().filter(|opcode| *opcode != OpCode::Igl).collect()
+    }
+
+    pub fn is_branch(&self) -> bool {
+        matches!(self,
+            OpCode::Call | OpCode::Jmp | OpCode::Je | OpCode::Jne | OpCode::Jle | OpCode::Jge | OpCode::Jl |
+            OpCode::Jg | OpCode::Jz | OpCode::Jnz | OpCode::Loop | OpCode::TailCall |
+            OpCode::Ja | OpCode::Jb | OpCode::Jnull | OpCode::Jnotnull)
+    }
+
+    pub fn sets_zero_flag(&self) -> bool {
+        matches!(self,
+            OpCode::Add | OpCode::Mul | OpCode::Sub | OpCode::Div | OpCode::Mod | OpCode::Inc | OpCode::Dec |
+            OpCode::Abs | OpCode::Sign | OpCode::Neg | OpCode::Adds | OpCode::Subs | OpCode::Muls | OpCode::Popcnt |
+            OpCode::Clz | OpCode::Ctz | OpCode::Bt | OpCode::Bts | OpCode::Btr | OpCode::Btc | OpCode::Shl |
+            OpCode::Shr | OpCode::Sar | OpCode::And | OpCode::Or | OpCode::Not | OpCode::Cmp | OpCode::Pow |
+            OpCode::Sqrt | OpCode::Strlen)
+    }
+
+    pub fn doc(&self) -> OpCodeDoc {
+        let (operands, stack, description) = match self {
+            OpCode::Push => ("value", "-- value", "Pushes an immediate value, or the value of a data symbol, onto the stack."),
+            OpCode::Pop => ("", "a --", "Discards the top of the stack."),
+            OpCode::Add => ("", "a b -- a+b", "Adds two integers, wrapping on overflow. Concatenates when either value is a string."),
+            OpCode::Mul => ("", "a b -- a*b", "Multiplies two integers, wrapping on overflow. Repeats a string b times when a is a string."),
+            OpCode::Sub => ("", "a b -- a-b", "Subtracts two integers, wrapping on overflow."),
+            OpCode::Div => ("", "a b -- a/b", "Divides two integers, failing when b is zero."),
+            OpCode::Mod => ("", "a b -- a%b", "Takes the remainder of dividing two integers, failing when b is zero."),
+            OpCode::Print => ("", "a --", "Prints the top of the stack."),
+            OpCode::Println => ("", "a --", "Prints the top of the stack followed by a newline."),
+            OpCode::Input => ("[int|uint|char]", "-- line", "Reads a line from stdin, optionally parsed as an int or uint or reduced to its first character, failing with a type_mismatch error when it cannot be. Requires the input capability."),
+            OpCode::Call => ("[@label|n]", "[target] --", "Calls a label or instruction index, pushing a new frame onto the call stack. Without an operand the target is popped from the stack."),
+            OpCode::Ret => ("[n]", "args... results -- results", "Returns to the instruction after the matching call, discarding the n arguments pushed before it."),
+            OpCode::Jmp => ("[@label|n|+n|-n]", "[target] --", "Jumps to a label, an instruction index or an offset relative to the current instruction. Without an operand the target is popped from the stack."),
+            OpCode::Je => ("@label", "a b --", "Jumps to a label when a equals b."),
+            OpCode::Jne => ("@label", "a b --", "Jumps to a label when a does not equal b."),
+            OpCode::Jle => ("@label", "a b --", "Jumps to a label when a is less than or equal to b."),
+            OpCode::Jge => ("@label", "a b --", "Jumps to a label when a is greater than or equal to b."),
+            OpCode::Jl => ("@label", "a b --", "Jumps to a label when a is less than b."),
+            OpCode::Jg => ("@label", "a b --", "Jumps to a label when a is greater than b."),
+            OpCode::Inc => ("", "a -- a+1", "Increments an integer, wrapping on overflow."),
+            OpCode::Dec => ("", "a -- a-1", "Decrements an integer, wrapping on overflow."),
+            OpCode::Nop => ("", "--", "Does nothing."),
+            OpCode::Hlt => ("[code]", "--", "Stops execution, setting the exit code when one is given."),
+            OpCode::Dup => ("", "a -- a a", "Duplicates the top of the stack."),
+            OpCode::Concat => ("", "a b -- ab", "Concatenates the string forms of two values."),
+            OpCode::Swap => ("", "a b -- b a", "Swaps the top two stack items."),
+            OpCode::Alloc => ("$var", "--", "Allocates a heap variable."),
+            OpCode::Free => ("$var", "--", "Frees a heap variable."),
+            OpCode::Load => ("$var|n", "-- value", "Pushes the value stored in a heap variable, or a copy of the stack item n places below the top."),
+            OpCode::Store => ("$var|n", "value --", "Stores a value into a heap variable, or into the stack item n places below the top once the value is popped."),
+            OpCode::Abs => ("", "a -- |a|", "Takes the absolute value of an integer."),
+            OpCode::Sign => ("", "a -- sign", "Pushes -1, 0 or 1 depending on the sign of an integer."),
+            OpCode::Adds => ("", "a b -- a+b", "Adds two integers, saturating at the integer bounds."),
+            OpCode::Subs => ("", "a b -- a-b", "Subtracts two integers, saturating at the integer bounds."),
+            OpCode::Popcnt => ("", "a -- count", "Counts the set bits of an integer."),
+            OpCode::Clz => ("", "a -- count", "Counts the leading zero bits of an integer."),
+            OpCode::Ctz => ("", "a -- count", "Counts the trailing zero bits of an integer."),
+            OpCode::Bt => ("", "a n -- bit", "Pushes 1 if bit n of a is set, otherwise 0."),
+            OpCode::Bts => ("", "a n -- a'", "Sets bit n of a."),
+            OpCode::Btr => ("", "a n -- a'", "Clears bit n of a."),
+            OpCode::Btc => ("", "a n -- a'", "Toggles bit n of a."),
+            OpCode::Arg => ("n", "-- value", "Copies the nth value below the current call frame, where 0 is the last value pushed before the call."),
+            OpCode::ArenaBegin => ("", "--", "Starts an arena; heap variables allocated until the matching arena_end belong to it."),
+            OpCode::ArenaEnd => ("", "--", "Frees every heap variable allocated in the current arena."),
+            OpCode::Shl => ("", "a n -- a<<n", "Shifts an integer left by n bits."),
+            OpCode::Shr => ("", "a n -- a>>n", "Shifts an integer right by n bits, filling with zeroes."),
+            OpCode::Sar => ("", "a n -- a>>n", "Shifts an integer right by n bits, preserving the sign."),
+            OpCode::And => ("", "a b -- a&b", "Bitwise and of two integers of the same type."),
+            OpCode::Or => ("", "a b -- a|b", "Bitwise or of two integers of the same type."),
+            OpCode::Not => ("", "a -- !a", "Bitwise not of an integer."),
+            OpCode::Neg => ("", "a -- -a", "Negates an integer, wrapping on overflow."),
+            OpCode::Cmp => ("[a] [b]", "a b -- order", "Compares two values and pushes -1, 0 or 1. Missing operands are popped from the stack; operands may be immediates or data symbols."),
+            OpCode::Jz => ("@label", "--", "Jumps to a label when the zero flag is set by the last arithmetic, bitwise or cmp instruction."),
+            OpCode::Jnz => ("@label", "--", "Jumps to a label when the zero flag is clear."),
+            OpCode::Loop => ("@label", "n -- n-1", "Decrements the counter on top of the stack and jumps to a label while it is still above zero; the counter is popped once it reaches zero, and a counter that starts at zero or below runs the body once."),
+            OpCode::Xchg => ("$a $b", "--", "Exchanges the values stored in two heap variables."),
+            OpCode::Cast => ("int|uint|str", "a -- a", "Converts the top of the stack to another type, failing when the value cannot be represented."),
+            OpCode::Enter => ("n", "-- 0...", "Reserves n zeroed local slots on the stack for the current call frame and records the frame pointer."),
+            OpCode::Leave => ("", "... --", "Discards everything on the stack above the frame pointer set by enter."),
+            OpCode::LocalGet => ("i", "-- local", "Pushes a copy of local slot i reserved by enter."),
+            OpCode::LocalSet => ("i", "a --", "Pops the top of the stack into local slot i reserved by enter."),
+            OpCode::Switch => ("@l0 @l1 ... @default", "n --", "Jumps to the nth label, or to the last label when n is out of range."),
+            OpCode::TailCall => ("@label", "--", "Jumps to a label reusing the current call frame, releasing any locals from enter, so the callee returns straight to the original caller."),
+            OpCode::Ja => ("@label", "a b --", "Jumps to a label when a is above b, comparing integers as unsigned."),
+            OpCode::Jb => ("@label", "a b --", "Jumps to a label when a is below b, comparing integers as unsigned."),
+            OpCode::Muls => ("", "a b -- a*b", "Multiplies two integers, saturating at the integer bounds."),
+            OpCode::Jnull => ("$var @label", "--", "Jumps to a label when a heap variable is not allocated or has no value stored."),
+            OpCode::Jnotnull => ("$var @label", "--", "Jumps to a label when a heap variable holds a value."),
+            OpCode::Pow => ("", "a b -- a^b", "Raises an integer to a non-negative integer power, wrapping on overflow."),
+            OpCode::Sqrt => ("", "a -- sqrt(a)", "Takes the integer square root of a non-negative integer, rounding down."),
+            OpCode::Rand => ("", "min max -- n", "Pushes a random integer between min and max inclusive."),
+            OpCode::Seed => ("", "n --", "Seeds the random number generator so rand produces a reproducible sequence."),
+            OpCode::Strlen => ("", "s -- len", "Pushes the number of characters in a string."),
+            OpCode::Substr => ("", "s start len -- sub", "Pushes len characters of a string starting at character start, failing when the range runs past the end."),
+            OpCode::CharAt => ("", "s i -- c", "Pushes the character at index i of a string as a one character string."),
+            OpCode::IndexOf => ("", "s needle -- i", "Pushes the character index of the first occurrence of needle in a string, or -1 when it does not occur."),
+            OpCode::Upper => ("", "s -- S", "Converts a string to upper case."),
+            OpCode::Lower => ("", "s -- s", "Converts a string to lower case."),
+            OpCode::Trim => ("", "s -- s", "Removes leading and trailing whitespace from a string."),
+            OpCode::Replace => ("", "s from to -- s", "Replaces every occurrence of from in a string with to."),
+            OpCode::Split => ("", "s delim -- parts... n", "Splits a string on a delimiter, pushing each part followed by the number of parts."),
+            OpCode::Join => ("", "parts... n delim -- s", "Pops n strings and joins them with a delimiter, in the order they were pushed."),
+            OpCode::Format => ("", "args... fmt -- s", "Replaces each {} in a format string with one of the values pushed before it, in the order they were pushed. {{ and }} produce literal braces."),
+            OpCode::Assert => ("", "a b --", "Fails with an assertion error showing both values, their types and a line diff for strings when a does not equal b."),
+            OpCode::FileOpen => ("", "path mode -- handle", "Opens a file for reading (r), writing (w) or appending (a) and pushes its handle. Requires the file capability."),
+            OpCode::FileRead => ("", "handle -- contents", "Reads the rest of a file opened for reading. Requires the file capability."),
+            OpCode::FileWrite => ("", "handle value --", "Writes the string form of a value to a file opened for writing. Requires the file capability."),
+            OpCode::FileClose => ("", "handle --", "Closes a file handle. Requires the file capability."),
+            OpCode::Env => ("", "name -- value", "Pushes the value of an environment variable, or an empty string when it is not set. Requires the env capability."),
+            OpCode::Args => ("", "-- args... n", "Pushes each command-line argument given after -- followed by the number of arguments. Requires the env capability."),
+            OpCode::Cwd => ("", "-- path", "Pushes the current working directory. Requires the env capability."),
+            OpCode::Sleep => ("", "ms --", "Pauses for a number of milliseconds, waking early with a cancelled error when the VM is cancelled."),
+            OpCode::HeapMark => ("", "--", "Records which heap variables are allocated so heap_diff can report changes since this point."),
+            OpCode::HeapDiff => ("", "-- diff", "Pushes the heap variables allocated (+) and freed (-) since heap_mark, e.g. \"+$a -$b\", or an empty string when nothing changed."),
+            OpCode::Igl => ("", "", "Illegal instruction produced for unknown opcodes.")
+        };
+        let flags = match self {
+            OpCode::Jz | OpCode::Jnz => "reads zero",
+            _ if self.sets_zero_flag() => "sets zero",
+            _ => ""
+        };
+        OpCodeDoc {
+            operands,
+            stack,
+            flags,
+            description,
+            example: self.example()
+        }
+    }
+
+    // A short program using the instruction.
+    fn example(&self) -> &'static str {
+        match self {
+            OpCode::Push => "push 42",
+            OpCode::Pop => "push 1\npop",
+            OpCode::Add => "push 2\npush 3\nadd",
+            OpCode::Mul => "push 6\npush 7\nmul",
+            OpCode::Sub => "push 5\npush 3\nsub",
+            OpCode::Div => "push 9\npush 2\ndiv",
+            OpCode::Mod => "push 9\npush 2\nmod",
+            OpCode::Print => "push \"Hello\"\nprint",
+            OpCode::Println => "push \"Hello\"\nprintln",
+            OpCode::Input => "input int",
+            OpCode::Call => "call @greet",
+            OpCode::Ret => "ret 2",
+            OpCode::Jmp => "jmp @loop",
+            OpCode::Je => "push 1\npush 1\nje @equal",
+            OpCode::Jne => "push 1\npush 2\njne @different",
+            OpCode::Jle => "push 1\npush 2\njle @smaller",
+            OpCode::Jge => "push 2\npush 1\njge @bigger",
+            OpCode::Jl => "push 1\npush 2\njl @smaller",
+            OpCode::Jg => "push 2\npush 1\njg @bigger",
+            OpCode::Inc => "push 41\ninc",
+            OpCode::Dec => "push 43\ndec",
+            OpCode::Nop => "nop",
+            OpCode::Hlt => "hlt 1",
+            OpCode::Dup => "push 42\ndup",
+            OpCode::Concat => "push \"Hello, \"\npush \"World!\"\nconcat",
+            OpCode::Igl => "",
+            OpCode::Swap => "push 0\npush 1\nswap",
+            OpCode::Alloc => "alloc $name",
+            OpCode::Free => "free $name",
+            OpCode::Load => "load $name",
+            OpCode::Store => "push \"opvm\"\nstore $name",
+            OpCode::Abs => "push -42\nabs",
+            OpCode::Sign => "push -42\nsign",
+            OpCode::Adds => "push 9223372036854775807\npush 1\nadds",
+            OpCode::Subs => "push -9223372036854775808\npush 1\nsubs",
+            OpCode::Popcnt => "push 7\npopcnt",
+            OpCode::Clz => "push 1\nclz",
+            OpCode::Ctz => "push 8\nctz",
+            OpCode::Bt => "push 5\npush 2\nbt",
+            OpCode::Bts => "push 5\npush 1\nbts",
+            OpCode::Btr => "push 5\npush 0\nbtr",
+            OpCode::Btc => "push 5\npush 2\nbtc",
+            OpCode::Arg => "arg 0",
+            OpCode::ArenaBegin => "arena_begin",
+            OpCode::ArenaEnd => "arena_end",
+            OpCode::Shl => "push 1\npush 4\nshl",
+            OpCode::Shr => "push 16\npush 4\nshr",
+            OpCode::Sar => "push -16\npush 2\nsar",
+            OpCode::And => "push 6\npush 3\nand",
+            OpCode::Or => "push 6\npush 3\nor",
+            OpCode::Not => "push 0\nnot",
+            OpCode::Neg => "push 42\nneg",
+            OpCode::Cmp => "push 1\npush 2\ncmp",
+            OpCode::Jz => "push 1\ndec\njz @done",
+            OpCode::Jnz => "push 2\ndec\njnz @again",
+            OpCode::Loop => "push 3\n.again\nloop @again",
+            OpCode::Xchg => "xchg $a $b",
+            OpCode::Cast => "push \"41\"\ncast int",
+            OpCode::Enter => "enter 2",
+            OpCode::Leave => "leave",
+            OpCode::LocalGet => "local.get 0",
+            OpCode::LocalSet => "push 42\nlocal.set 0",
+            OpCode::Switch => "push 1\nswitch @zero @one @other",
+            OpCode::TailCall => "tailcall @loop",
+            OpCode::Ja => "push -1\npush 1\nja @above",
+            OpCode::Jb => "push 1\npush -1\njb @below",
+            OpCode::Muls => "push 9223372036854775807\npush 2\nmuls",
+            OpCode::Jnull => "jnull $name @missing",
+            OpCode::Jnotnull => "jnotnull $name @found",
+            OpCode::Pow => "push 2\npush 10\npow",
+            OpCode::Sqrt => "push 17\nsqrt",
+            OpCode::Rand => "push 1\npush 6\nrand",
+            OpCode::Seed => "push 42\nseed",
+            OpCode::Strlen => "push \"opvm\"\nstrlen",
+            OpCode::Substr => "push \"opvm\"\npush 2\npush 2\nsubstr",
+            OpCode::CharAt => "push \"opvm\"\npush 0\ncharat",
+            OpCode::IndexOf => "push \"opvm\"\npush \"vm\"\nindexof",
+            OpCode::Upper => "push \"opvm\"\nupper",
+            OpCode::Lower => "push \"OPVM\"\nlower",
+            OpCode::Trim => "push \"  opvm  \"\ntrim",
+            OpCode::Replace => "push \"a-b-c\"\npush \"-\"\npush \"+\"\nreplace",
+            OpCode::Split => "push \"a,b,c\"\npush \",\"\nsplit",
+            OpCode::Join => "push \"a\"\npush \"b\"\npush 2\npush \",\"\njoin",
+            OpCode::Format => "push \"World\"\npush \"Hello, {}!\"\nformat",
+            OpCode::Assert => "push 4\npush 4\nassert",
+            OpCode::FileOpen => "push \"out.txt\"\npush \"w\"\nfile_open",
+            OpCode::FileRead => "load $handle\nfile_read",
+            OpCode::FileWrite => "load $handle\npush \"Hello\"\nfile_write",
+            OpCode::FileClose => "load $handle\nfile_close",
+            OpCode::Env => "push \"HOME\"\nenv",
+            OpCode::Args => "args",
+            OpCode::Cwd => "cwd",
+            OpCode::Sleep => "push 100\nsleep",
+            OpCode::HeapMark => "heap_mark",
+            OpCode::HeapDiff => "heap_mark\nalloc $a\nheap_diff"
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Capability {
+    Input,
+    File,
+    Env
+}
+
+impl Capability {
+    // Everything a program gets when the host doesn't say otherwise. File and environment access aren't included and
+    // have to be granted explicitly.
+    pub fn all() -> Vec<Capability> {
+        vec![Capability::Input]
+    }
+
+    pub fn from_name(name: &str) -> Option<Capability> {
+        match name {
+            "input" => Some(Capability::Input),
+            "file" => Some(Capability::File),
+            "env" => Some(Capability::Env),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VmOptions {
+    pub reflection: bool,
+    pub error_window: usize,
+    pub error_color: bool,
+    pub heap_size: Option<usize>,
+    pub capabilities: Vec<Capability>,
+    pub trap_fallthrough: bool,
+    pub trap_on_overflow: bool,
+    pub seed: Option<u64>,
+    pub profile: bool,
+    pub args: Vec<String>
+}
+
+impl VmOptions {
+    pub fn deterministic(seed: u64) -> Self {
+        VmOptions { seed: Some(seed), ..Default::default() }
+    }
+}
+
+impl Default for VmOptions {
+    fn default() -> Self {
+        VmOptions {
+            reflection: true,
+            error_window: 4,
+            error_color: false,
+            heap_size: None,
+            capabilities: Capability::all(),
+            trap_fallthrough: false,
+            trap_on_overflow: false,
+            seed: None,
+            profile: false,
+            args: vec![]
+        }
+    }
+}
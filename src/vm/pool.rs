@@ -0,0 +1,80 @@
+use crate::vm::error::Error;
+use crate::vm::options::VmOptions;
+use crate::vm::program::Program;
+use crate::vm::snapshot::Snapshot;
+use crate::vm::vm::Vm;
+
+// Keeps reset VMs around so many short programs don't each pay for building one.
+pub struct VmPool {
+    options: VmOptions,
+    capacity: usize,
+    idle: Vec<Vm>
+}
+
+impl VmPool {
+    pub fn new(options: VmOptions, capacity: usize) -> Self {
+        let idle = (0..capacity).map(|_| Vm::with_options(options.clone())).collect();
+        VmPool { options, capacity, idle }
+    }
+
+    pub fn idle(&self) -> usize {
+        self.idle.len()
+    }
+
+    pub fn acquire(&mut self) -> Vm {
+        self.idle.pop().unwrap_or_else(|| Vm::with_options(self.options.clone()))
+    }
+
+    pub fn release(&mut self, mut vm: Vm) {
+        if self.idle.len() < self.capacity {
+            vm.reset();
+            self.idle.push(vm);
+        }
+    }
+
+    pub fn execute(&mut self, program: Program) -> Result<Snapshot, Error> {
+        let mut vm = self.acquire();
+        let result = vm.execute(program).map(|_| vm.snapshot());
+        self.release(vm);
+        result
+    }
+
+    pub fn execute_all(&mut self, programs: Vec<Program>) -> Vec<Result<Snapshot, Error>> {
+        programs.into_iter().map(|program| self.execute(program)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::error::ErrorKind;
+    use crate::vm::field::Field;
+    use crate::vm::instruction::Instruction;
+    use crate::vm::opcode::OpCode;
+
+    fn program(instructions: Vec<Instruction>) -> Program {
+        Program { instructions, ..Default::default() }
+    }
+
+    #[test]
+    fn test_execute_all() {
+        let mut pool = VmPool::new(VmOptions { reflection: false, ..Default::default() }, 1);
+        let results = pool.execute_all(vec![
+            program(vec![
+                Instruction::new(OpCode::Alloc, vec![Field::from("$a")]),
+                Instruction::new(OpCode::Push, vec![Field::from(1)]),
+            ]),
+            program(vec![
+                Instruction::new(OpCode::Pop, vec![]),
+            ]),
+            program(vec![
+                Instruction::new(OpCode::Alloc, vec![Field::from("$a")]),
+            ]),
+        ]);
+
+        assert_eq!(results[0].as_ref().unwrap().stack, vec![Field::from(1)]);
+        assert_eq!(results[1].as_ref().err().unwrap().kind, ErrorKind::StackUnderflow);
+        assert!(results[2].as_ref().unwrap().stack.is_empty());
+        assert_eq!(pool.idle(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+
+pub const ROOT_FRAME: &str = "main";
+
+// Instruction counts keyed by the call stack they ran under, e.g. "main;outer;inner".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    samples: BTreeMap<String, u64>
+}
+
+impl Profile {
+    pub fn record<'a>(&mut self, frames: impl Iterator<Item = &'a str>) {
+        let mut stack = ROOT_FRAME.to_string();
+        for frame in frames {
+            stack.push(';');
+            stack.push_str(frame.trim_start_matches('@'));
+        }
+        *self.samples.entry(stack).or_insert(0) += 1;
+    }
+
+    pub fn samples(&self) -> &BTreeMap<String, u64> {
+        &self.samples
+    }
+
+    // The folded stack format read by inferno and flamegraph.pl.
+    pub fn folded(&self) -> String {
+        self.samples.iter().map(|(stack, count)| format!("{} {}\n", stack, count)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_folded() {
+        let mut profile = Profile::default();
+        profile.record(vec![].into_iter());
+        profile.record(vec!["@outer", "@inner"].into_iter());
+        profile.record(vec!["@outer", "@inner"].into_iter());
+
+        assert_eq!(profile.folded(), "main 1\nmain;outer;inner 2\n");
+    }
+}
@@ -1,16 +1,458 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
 use crate::vm::field::Field;
 use crate::vm::instruction::Instruction;
+use crate::vm::opcode::OpCode;
 
-#[derive(Debug)]
+pub const DEFAULT_ENTRY: &str = "@main";
+pub const REFLECTION_PREFIX: &str = "$__";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proc {
+    pub name: String,
+    pub start: usize,
+    pub end: usize
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub labels: HashMap<String, usize>,
-    pub data: HashMap<String, Field>
+    pub data: HashMap<String, Field>,
+    pub procs: Vec<Proc>,
+    pub entry: Option<String>,
+    pub init: Vec<usize>,
+    pub fini: Vec<usize>,
+    // Pushes of an unquoted @symbol from files lexed in strict mode, which must name a label or data symbol.
+    pub strict_symbols: Vec<usize>,
+    pub label_offsets: Vec<LabelOffset>
+}
+
+// An unquoted `@label+N` or `@label-N` branch operand, left as written until every file is linked and the label
+// can be found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelOffset {
+    pub pc: usize,
+    pub operand: usize,
+    pub label: String,
+    pub offset: i64
 }
 
 impl Program {
     pub fn new() -> Self {
-        Program{ instructions: vec![], labels: Default::default(), data: Default::default() }
+        Program{ instructions: vec![], labels: Default::default(), data: Default::default(), procs: vec![], entry: None, init: vec![], fini: vec![], strict_symbols: vec![], label_offsets: vec![] }
+    }
+
+    pub fn entry_label(&self) -> Option<String> {
+        match &self.entry {
+            Some(entry) => Some(entry.clone()),
+            None if self.labels.contains_key(DEFAULT_ENTRY) => Some(DEFAULT_ENTRY.to_string()),
+            None => None
+        }
+    }
+
+    pub fn uses_reflection(&self) -> bool {
+        self.instructions.iter()
+            .flat_map(|i| i.operand.to_vec())
+            .any(|o| o.to_str().is_some_and(|s| s.starts_with(REFLECTION_PREFIX)))
+    }
+
+    // Makes identical string constants share one allocation, so a repeated literal only costs a pointer. Returns how
+    // many operands were pointed at an existing constant.
+    pub fn dedupe_constants(&mut self) -> usize {
+        let mut pool: HashSet<Rc<str>> = HashSet::new();
+        let mut shared = 0;
+        let mut intern = |field: &mut Field| {
+            if let Field::S(s) = field {
+                match pool.get(s) {
+                    Some(existing) if !Rc::ptr_eq(existing, s) => {
+                        *s = existing.clone();
+                        shared += 1;
+                    }
+                    Some(_) => {}
+                    None => {
+                        pool.insert(s.clone());
+                    }
+                }
+            }
+        };
+        for instruction in self.instructions.iter_mut() {
+            for i in 0..instruction.operand.len() {
+                if let Some(operand) = instruction.operand.get_mut(i) {
+                    intern(operand);
+                }
+            }
+        }
+        for value in self.data.values_mut() {
+            intern(value);
+        }
+        shared
+    }
+
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        for instruction in self.instructions.iter() {
+            let opcode: &str = instruction.opcode.into();
+            hasher.write_str(opcode);
+            hasher.write(&(instruction.operand.len() as u64).to_le_bytes());
+            for operand in instruction.operand.to_vec() {
+                hasher.write_field(operand);
+            }
+        }
+
+        let mut labels: Vec<(&String, &usize)> = self.labels.iter().collect();
+        labels.sort();
+        for (label, pc) in labels {
+            hasher.write_str(label);
+            hasher.write(&(*pc as u64).to_le_bytes());
+        }
+
+        let mut data: Vec<(&String, &Field)> = self.data.iter().collect();
+        data.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, value) in data {
+            hasher.write_str(name);
+            hasher.write_field(value);
+        }
+
+        for proc in self.procs.iter() {
+            hasher.write_str(&proc.name);
+            hasher.write(&(proc.start as u64).to_le_bytes());
+            hasher.write(&(proc.end as u64).to_le_bytes());
+        }
+
+        if let Some(entry) = &self.entry {
+            hasher.write_str(entry);
+        }
+        for pc in self.init.iter() {
+            hasher.write(b"init");
+            hasher.write(&(*pc as u64).to_le_bytes());
+        }
+        for pc in self.fini.iter() {
+            hasher.write(b"fini");
+            hasher.write(&(*pc as u64).to_le_bytes());
+        }
+        for pc in self.strict_symbols.iter() {
+            hasher.write(b"strict");
+            hasher.write(&(*pc as u64).to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    pub fn functions(&self) -> BTreeMap<usize, String> {
+        let mut functions: BTreeMap<usize, String> = self.procs.iter().map(|p| (p.start, p.name.clone())).collect();
+        for instruction in self.instructions.iter().filter(|i| matches!(i.opcode, OpCode::Call | OpCode::TailCall)) {
+            for operand in instruction.operand.to_vec() {
+                if let Some(pc) = self.target(operand) {
+                    functions.insert(pc, operand.to_string());
+                }
+            }
+        }
+        functions
+    }
+
+    pub fn verify(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (pc, name) in self.functions() {
+            let previous = match pc.checked_sub(1).and_then(|p| self.instructions.get(p)) {
+                Some(i) => i,
+                None => continue
+            };
+            if !matches!(previous.opcode, OpCode::Ret | OpCode::Jmp | OpCode::TailCall | OpCode::Hlt) {
+                let line = previous.line.map(|l| format!(" (line {})", l)).unwrap_or_default();
+                warnings.push(format!("Warning: instruction {}{} falls through into function {}.", pc - 1, line, name));
+            }
+        }
+        for proc in self.procs.iter() {
+            if let Some(pc) = self.unreturned_path(proc) {
+                warnings.push(format!("Warning: procedure {} can reach endp without ret from instruction {}.", proc.name, pc));
+            }
+        }
+        warnings
+    }
+
+    fn target(&self, operand: &Field) -> Option<usize> {
+        match operand {
+            Field::S(label) => self.labels.get(label.as_ref()).copied(),
+            Field::I(i) if *i >= 0 => Some(*i as usize),
+            Field::U(u) => Some(*u),
+            _ => None
+        }
+    }
+
+    fn unreturned_path(&self, proc: &Proc) -> Option<usize> {
+        let mut visited = vec![false; proc.end - proc.start];
+        let mut pending = vec![proc.start];
+        while let Some(pc) = pending.pop() {
+            if pc < proc.start || pc >= proc.end || visited[pc - proc.start] {
+                continue;
+            }
+            visited[pc - proc.start] = true;
+            let instruction = match self.instructions.get(pc) {
+                Some(i) => i,
+                None => return Some(pc)
+            };
+            let target = instruction.operand.to_vec().last().and_then(|o| self.target(o));
+            let successors = match instruction.opcode {
+                OpCode::Ret | OpCode::Hlt => vec![],
+                OpCode::Jmp => target.into_iter().collect(),
+                OpCode::TailCall => vec![],
+                OpCode::Call => vec![pc + 1],
+                OpCode::Switch => instruction.operand.to_vec().iter().filter_map(|o| self.target(o)).collect(),
+                op if op.is_branch() => target.into_iter().chain(Some(pc + 1)).collect(),
+                _ => vec![pc + 1]
+            };
+            for next in successors {
+                if next == proc.end {
+                    return Some(pc);
+                }
+                pending.push(next);
+            }
+        }
+        None
+    }
+
+    // Replaces each label offset with the absolute instruction index it refers to, the same as a numeric jump target.
+    // Run once the whole program is linked, since the label may be defined by another file.
+    pub fn resolve_label_offsets(&mut self) -> Result<(), String> {
+        for label_offset in self.label_offsets.drain(..) {
+            let target = match self.labels.get(&label_offset.label) {
+                Some(pc) => *pc as i64 + label_offset.offset,
+                None => return Err(format!("Unknown label {} in {}{:+}.", label_offset.label, label_offset.label, label_offset.offset))
+            };
+            if target < 0 || target as usize > self.instructions.len() {
+                return Err(format!("{}{:+} is outside of the program.", label_offset.label, label_offset.offset));
+            }
+            if let Some(operand) = self.instructions[label_offset.pc].operand.get_mut(label_offset.operand) {
+                *operand = Field::from(target);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn link(&mut self, other: Program) -> Result<(), String> {
+        for label in other.labels.keys().chain(other.data.keys()) {
+            if self.labels.contains_key(label) || self.data.contains_key(label) {
+                return Err(format!("Duplicate symbol {} while linking.", label));
+            }
+        }
+        if self.entry.is_some() && other.entry.is_some() {
+            return Err("Duplicate entry directive while linking.".to_string());
+        }
+
+        let offset = self.instructions.len();
+        for (label, pc) in other.labels {
+            self.labels.insert(label, pc + offset);
+        }
+        for proc in other.procs {
+            self.procs.push(Proc { start: proc.start + offset, end: proc.end + offset, ..proc });
+        }
+        self.data.extend(other.data);
+        self.entry = self.entry.take().or(other.entry);
+        self.init.extend(other.init.iter().map(|pc| pc + offset));
+        self.fini.extend(other.fini.iter().map(|pc| pc + offset));
+        self.strict_symbols.extend(other.strict_symbols.iter().map(|pc| pc + offset));
+        self.label_offsets.extend(other.label_offsets.into_iter().map(|l| LabelOffset { pc: l.pc + offset, ..l }));
+        for mut instruction in other.instructions {
+            // Numeric targets, including relative ones the lexer made absolute, index into the file they came from.
+            if instruction.opcode.is_branch() || instruction.opcode == OpCode::Switch {
+                for i in 0..instruction.operand.len() {
+                    match instruction.operand.get_mut(i) {
+                        Some(Field::I(target)) => *target += offset as i64,
+                        Some(Field::U(target)) => *target += offset,
+                        _ => ()
+                    }
+                }
+            }
+            self.instructions.push(instruction);
+        }
+        self.dedupe_constants();
+        Ok(())
+    }
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
     }
-}
\ No newline at end of file
+
+    fn write_field(&mut self, field: &Field) {
+        match field {
+            Field::I(i) => {
+                self.write(b"i");
+                self.write(&i.to_le_bytes());
+            }
+            Field::U(u) => {
+                self.write(b"u");
+                self.write(&(*u as u64).to_le_bytes());
+            }
+            Field::S(s) => {
+                self.write(b"s");
+                self.write_str(s);
+            }
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_dedupe_constants() {
+        let mut program = Program::new();
+        for _ in 0..3 {
+            program.instructions.push(Instruction::new(OpCode::Push, vec![Field::from("hello")]));
+        }
+        program.data.insert("@greeting".to_string(), Field::from("hello"));
+
+        assert_eq!(program.dedupe_constants(), 3);
+        assert_eq!(program.dedupe_constants(), 0);
+        let first = match program.instructions[0].operand.get(0) {
+            Some(Field::S(s)) => s.clone(),
+            _ => panic!("expected a string operand")
+        };
+        assert!(matches!(program.data.get("@greeting"), Some(Field::S(s)) if Rc::ptr_eq(s, &first)));
+    }
+
+    #[test]
+    fn can_link_programs() {
+        let mut program = Program::new();
+        program.instructions.push(Instruction::new(OpCode::Call, vec![Field::from("@lib")]));
+        program.labels.insert("@main".to_string(), 0);
+
+        let mut lib = Program::new();
+        lib.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        lib.labels.insert("@lib".to_string(), 0);
+        lib.data.insert("@value".to_string(), Field::from(1));
+        lib.strict_symbols.push(0);
+
+        assert!(program.link(lib).is_ok());
+        assert_eq!(program.instructions.len(), 2);
+        assert_eq!(program.strict_symbols, vec![1]);
+        assert_eq!(*program.labels.get("@lib").unwrap(), 1);
+        assert_eq!(*program.data.get("@value").unwrap(), Field::from(1));
+
+        let mut entry = Program::new();
+        entry.entry = Some("@main".to_string());
+        assert!(program.link(entry.clone()).is_ok());
+        assert_eq!(program.entry, Some("@main".to_string()));
+        assert!(program.link(entry).is_err());
+    }
+
+    #[test]
+    fn can_link_numeric_branch_targets() {
+        let mut program = Program::new();
+        program.instructions.push(Instruction::new(OpCode::Hlt, vec![]));
+
+        let mut lib = Program::new();
+        lib.instructions.push(Instruction::new(OpCode::Jmp, vec![Field::from(2)]));
+        lib.instructions.push(Instruction::new(OpCode::Switch, vec![Field::from("@a"), Field::from(0_usize)]));
+        lib.instructions.push(Instruction::new(OpCode::Push, vec![Field::from(2)]));
+
+        assert!(program.link(lib).is_ok());
+        assert_eq!(program.instructions[1].operand.to_vec(), &vec![Field::from(3)]);
+        assert_eq!(program.instructions[2].operand.to_vec(), &vec![Field::from("@a"), Field::from(1_usize)]);
+        assert_eq!(program.instructions[3].operand.to_vec(), &vec![Field::from(2)]);
+    }
+
+    #[test]
+    fn can_fingerprint_programs() {
+        let build = |value: Field, labels: Vec<(&str, usize)>| {
+            let mut program = Program::new();
+            program.instructions.push(Instruction::new(OpCode::Push, vec![value]));
+            program.instructions.push(Instruction::new(OpCode::Println, vec![]));
+            for (label, pc) in labels {
+                program.labels.insert(label.to_string(), pc);
+            }
+            program
+        };
+
+        let program = build(Field::from(1), vec![("@a", 0), ("@b", 1)]);
+        assert_eq!(program.fingerprint(), build(Field::from(1), vec![("@b", 1), ("@a", 0)]).fingerprint());
+        assert_eq!(program.fingerprint(), 0xd0da29c9769b879e);
+        assert_ne!(program.fingerprint(), build(Field::from(1_usize), vec![("@a", 0), ("@b", 1)]).fingerprint());
+        assert_ne!(program.fingerprint(), build(Field::from("1"), vec![("@a", 0), ("@b", 1)]).fingerprint());
+        assert_ne!(program.fingerprint(), build(Field::from(1), vec![("@a", 1), ("@b", 1)]).fingerprint());
+    }
+
+    #[test]
+    fn cannot_link_duplicate_symbols() {
+        let mut program = Program::new();
+        program.labels.insert("@main".to_string(), 0);
+
+        let mut lib = Program::new();
+        lib.data.insert("@main".to_string(), Field::from(1));
+
+        assert!(program.link(lib).is_err());
+    }
+
+    #[test]
+    fn can_verify_function_fallthrough() {
+        let mut program = Program::new();
+        program.instructions.push(Instruction::new(OpCode::Call, vec![Field::from("@a")]));
+        program.instructions.push(Instruction::new(OpCode::Call, vec![Field::from("@b")]));
+        program.instructions.push(Instruction::new(OpCode::Push, vec![Field::from(1)]));
+        program.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        program.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        program.labels.insert("@a".to_string(), 3);
+        program.labels.insert("@b".to_string(), 4);
+
+        let warnings = program.verify();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0], "Warning: instruction 2 falls through into function @a.");
+    }
+
+    #[test]
+    fn can_verify_proc_returns() {
+        let mut program = Program::new();
+        program.instructions.push(Instruction::new(OpCode::Jz, vec![Field::from("@done")]));
+        program.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        program.instructions.push(Instruction::new(OpCode::Push, vec![Field::from(1)]));
+        program.labels.insert("@done".to_string(), 2);
+        program.procs.push(Proc { name: "@f".to_string(), start: 0, end: 3 });
+
+        let warnings = program.verify();
+        assert_eq!(warnings, vec!["Warning: procedure @f can reach endp without ret from instruction 2."]);
+
+        program.instructions.push(Instruction::new(OpCode::Ret, vec![]));
+        program.procs[0].end = 4;
+        assert!(program.verify().is_empty());
+    }
+
+    #[test]
+    fn can_resolve_entry_label() {
+        let mut program = Program::new();
+        assert_eq!(program.entry_label(), None);
+        program.labels.insert("@main".to_string(), 2);
+        assert_eq!(program.entry_label(), Some("@main".to_string()));
+        program.entry = Some("@start".to_string());
+        assert_eq!(program.entry_label(), Some("@start".to_string()));
+    }
+
+    #[test]
+    fn can_detect_reflection() {
+        let mut program = Program::new();
+        program.instructions.push(Instruction::new(OpCode::Push, vec![Field::from("$stack_size")]));
+        assert!(!program.uses_reflection());
+        program.instructions.push(Instruction::new(OpCode::Load, vec![Field::from("$__stack_size")]));
+        assert!(program.uses_reflection());
+    }
+}
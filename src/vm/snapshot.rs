@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::vm::field::Field;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub zero: bool,
+    pub stack: Vec<Field>,
+    pub call_stack: Vec<String>,
+    pub heap: BTreeMap<String, Option<Field>>
+}
+
+impl Snapshot {
+    pub fn diff(&self, other: &Snapshot) -> Option<String> {
+        if self.zero != other.zero {
+            return Some(format!("zero flag differs: {} != {}", self.zero, other.zero));
+        }
+
+        for i in 0..self.stack.len().max(other.stack.len()) {
+            let left = self.stack.get(i);
+            let right = other.stack.get(i);
+            if left != right {
+                return Some(format!("stack[{}] differs: {} != {}", i, describe(left), describe(right)));
+            }
+        }
+
+        if self.call_stack != other.call_stack {
+            return Some(format!("call stack differs: [{}] != [{}]", self.call_stack.join(", "), other.call_stack.join(", ")));
+        }
+
+        for key in self.heap.keys().chain(other.heap.keys()) {
+            let left = self.heap.get(key);
+            let right = other.heap.get(key);
+            if left != right {
+                return Some(format!("heap {} differs: {} != {}", key, describe_heap(left), describe_heap(right)));
+            }
+        }
+        None
+    }
+}
+
+// Heap variables allocated and freed between a heap mark and a later point in the run. Anything still in
+// `allocated` at the end of a routine was leaked by it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeapDiff {
+    pub allocated: Vec<String>,
+    pub freed: Vec<String>
+}
+
+impl HeapDiff {
+    pub fn between(before: &BTreeSet<String>, after: &BTreeSet<String>) -> Self {
+        HeapDiff {
+            allocated: after.difference(before).cloned().collect(),
+            freed: before.difference(after).cloned().collect()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocated.is_empty() && self.freed.is_empty()
+    }
+}
+
+impl fmt::Display for HeapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = self.allocated.iter().map(|v| format!("+{}", v))
+            .chain(self.freed.iter().map(|v| format!("-{}", v)))
+            .collect();
+        write!(f, "{}", changes.join(" "))
+    }
+}
+
+fn describe(field: Option<&Field>) -> String {
+    match field {
+        Some(f) => format!("{:?}", f),
+        None => "missing".to_string()
+    }
+}
+
+fn describe_heap(item: Option<&Option<Field>>) -> String {
+    match item {
+        Some(Some(f)) => format!("{:?}", f),
+        Some(None) => "unset".to_string(),
+        None => "unallocated".to_string()
+    }
+}
@@ -22,6 +22,30 @@ impl<T> Stack<T> {
         self.0.pop()
     }
 
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        self.0.remove(index)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.0.last()
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.0.last_mut()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.0.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.0.get_mut(index)
+    }
+
     pub fn to_vec(&self) -> &Vec<T> {
         &self.0
     }
@@ -0,0 +1,18 @@
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub instructions: u64,
+    pub heap_entries: usize,
+    pub heap_bytes: usize,
+    pub peak_heap_entries: usize,
+    pub peak_heap_bytes: usize
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "instructions executed: {}", self.instructions)?;
+        writeln!(f, "heap entries: {} (peak {})", self.heap_entries, self.peak_heap_entries)?;
+        write!(f, "heap bytes: {} (peak {})", self.heap_bytes, self.peak_heap_bytes)
+    }
+}
@@ -0,0 +1,9 @@
+use crate::vm::error::Error;
+use crate::vm::stats::Stats;
+
+// Implemented by hosts that want to forward VM metrics to their own monitoring.
+pub trait Telemetry {
+    fn finished(&mut self, _stats: &Stats) {}
+
+    fn failed(&mut self, _error: &Error, _stats: &Stats) {}
+}
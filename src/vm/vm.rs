@@ -1,28 +1,76 @@
-use std::{cmp, io};
+use std::{cmp, env, io};
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
 use crate::vm::instruction::Instruction;
 use crate::vm::opcode::OpCode;
 use crate::vm::field::Field;
-use std::collections::HashMap;
-use crate::vm::error::Error;
-use crate::vm::program::Program;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use crate::vm::error::{Error, ErrorKind};
+use crate::vm::program::{Program, REFLECTION_PREFIX};
 use crate::vm::stack;
 use crate::vm::stack::Stack;
 use crate::vm::heap::Heap;
-use std::io::Write;
+use crate::vm::rng::Rng;
+use crate::vm::frame::Frame;
+use crate::vm::options::{Capability, VmOptions};
+use crate::vm::snapshot::{HeapDiff, Snapshot};
+use crate::vm::stats::Stats;
+use crate::vm::profile::Profile;
+use crate::vm::telemetry::Telemetry;
+use crate::vm::cancel::CancelHandle;
+use crate::vm::diff;
+use crate::vm::streams::CapturedOutput;
+use crate::vm::files::FileTable;
+use crate::vm::exit::ExitStatus;
+use std::io::{BufRead, BufReader, Write};
+
+const STACK_SIZE_VAR: &str = "$__stack_size";
+const CALLSTACK_SIZE_VAR: &str = "$__callstack_size";
+const PC_VAR: &str = "$__pc";
+const CANCEL_CHECK_INTERVAL: u64 = 1024;
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+const MAX_LOCALS: i64 = 65536;
+const MAX_STRING_LEN: usize = 16 * 1024 * 1024;
 
 pub struct Vm {
     instructions: Vec<Instruction>,
     labels: HashMap<String,usize>,
     data: HashMap<String, Field>,
     stack: stack::Stack<Field>,
-    call_stack: stack::Stack<usize>,
+    call_stack: stack::Stack<Frame>,
     pc: usize,
+    zero: bool,
     heap: HashMap<String,Heap>,
-    reflection: bool
+    arenas: stack::Stack<Vec<String>>,
+    functions: BTreeMap<usize, String>,
+    label_cache: Vec<Option<usize>>,
+    data_cache: Vec<Option<Field>>,
+    rng: Rng,
+    heap_bytes: usize,
+    stats: Stats,
+    profile: Profile,
+    telemetry: Option<Box<dyn Telemetry>>,
+    cancel: CancelHandle,
+    stdout: Box<dyn Write>,
+    stdin: Box<dyn BufRead>,
+    captured: Option<CapturedOutput>,
+    files: FileTable,
+    strict_symbols: HashSet<usize>,
+    exit_code: i64,
+    halted: bool,
+    heap_mark: Option<BTreeSet<String>>,
+    shutdown_hooks: Vec<Box<dyn FnOnce()>>,
+    options: VmOptions
 }
 
 impl Vm {
     pub fn new(reflection: bool) -> Self {
+        Vm::with_options(VmOptions { reflection, ..Default::default() })
+    }
+
+    pub fn with_options(options: VmOptions) -> Self {
         Vm{
             instructions: vec![],
             labels: HashMap::new(),
@@ -30,55 +78,356 @@ impl Vm {
             stack: stack::Stack::new(),
             call_stack: stack::Stack::new(),
             pc: 0,
+            zero: false,
             heap: HashMap::new(),
-            reflection
+            arenas: stack::Stack::new(),
+            functions: BTreeMap::new(),
+            label_cache: vec![],
+            data_cache: vec![],
+            rng: options.seed.map(Rng::new).unwrap_or_else(Rng::from_time),
+            heap_bytes: 0,
+            stats: Stats::default(),
+            profile: Profile::default(),
+            telemetry: None,
+            cancel: CancelHandle::default(),
+            stdout: Box::new(io::stdout()),
+            stdin: Box::new(BufReader::new(io::stdin())),
+            captured: None,
+            files: FileTable::default(),
+            strict_symbols: HashSet::new(),
+            exit_code: 0,
+            halted: false,
+            heap_mark: None,
+            shutdown_hooks: vec![],
+            options
         }
     }
 
     pub fn reset(&mut self) {
         self.heap.clear();
+        self.heap_bytes = 0;
+        self.stats = Stats::default();
+        self.profile = Profile::default();
+        self.cancel.clear();
+        self.files.clear();
+        self.exit_code = 0;
+        self.halted = false;
+        self.heap_mark = None;
+        if let Some(seed) = self.options.seed {
+            self.rng = Rng::new(seed);
+        }
+        self.zero = false;
+
+        while !self.arenas.is_empty() {
+            self.arenas.pop();
+        }
 
-        while self.stack.len() > 0 {
+        while !self.stack.is_empty() {
             self.stack.pop();
         }
 
-        while self.call_stack.len() > 0 {
+        while !self.call_stack.is_empty() {
             self.call_stack.pop();
         }
     }
 
-    pub fn execute(&mut self, program: Program) -> Result<(), Error> {
+    pub fn stats(&self) -> Stats {
+        Stats {
+            heap_entries: self.heap.len(),
+            heap_bytes: self.heap_bytes,
+            ..self.stats.clone()
+        }
+    }
+
+    pub fn on_shutdown(&mut self, hook: impl FnOnce() + 'static) {
+        self.shutdown_hooks.push(Box::new(hook));
+    }
+
+    // Releases everything the program still holds and runs the host's shutdown hooks. Dropping the VM does this
+    // too, so calling it is only needed to release resources earlier.
+    pub fn shutdown(&mut self) {
+        self.files.clear();
+        self.heap.clear();
+        self.heap_bytes = 0;
+        self.arenas.truncate(0);
+        let _ = self.stdout.flush();
+        for hook in self.shutdown_hooks.drain(..) {
+            hook();
+        }
+    }
+
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+        self.captured = None;
+    }
+
+    pub fn set_stdin(&mut self, stdin: impl BufRead + 'static) {
+        self.stdin = Box::new(stdin);
+    }
+
+    pub fn capture_output(&mut self) {
+        let captured = CapturedOutput::default();
+        self.stdout = Box::new(captured.clone());
+        self.captured = Some(captured);
+    }
+
+    pub fn take_output(&mut self) -> String {
+        self.captured.as_ref().map(|c| c.take()).unwrap_or_default()
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn set_telemetry(&mut self, telemetry: Box<dyn Telemetry>) {
+        self.telemetry = Some(telemetry);
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn heap_mark(&mut self) {
+        self.heap_mark = Some(self.heap_names());
+    }
+
+    pub fn heap_diff(&self) -> Option<HeapDiff> {
+        self.heap_mark.as_ref().map(|mark| HeapDiff::between(mark, &self.heap_names()))
+    }
+
+    fn heap_names(&self) -> BTreeSet<String> {
+        self.heap.keys().filter(|name| !name.starts_with(REFLECTION_PREFIX)).cloned().collect()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            zero: self.zero,
+            stack: self.stack.to_vec().clone(),
+            call_stack: self.call_stack.to_vec().iter().map(|f| f.label.clone()).collect(),
+            heap: self.heap.iter()
+                .filter(|(name, _)| !name.starts_with(REFLECTION_PREFIX))
+                .map(|(name, heap)| (name.clone(), heap.item.as_ref().map(|i| *i.clone())))
+                .collect()
+        }
+    }
+
+    // Steps both programs together and reports the first instruction after which their state differs.
+    pub fn diff_runs(before: Program, after: Program, options: VmOptions) -> Result<Option<String>, Error> {
+        let mut vm = Vm::with_options(options.clone());
+        let fini = vm.start(before)?;
+        let mut other = Vm::with_options(options);
+        let other_fini = other.start(after)?;
+        if let Some(diff) = vm.snapshot().diff(&other.snapshot()) {
+            return Ok(Some(format!("before main: {}", diff)));
+        }
+
+        loop {
+            let left = vm.current();
+            let right = other.current();
+            let stepped = vm.step()? | other.step()?;
+            if !stepped {
+                break;
+            }
+            if let Some(diff) = vm.snapshot().diff(&other.snapshot()) {
+                let at = if left == right { left } else { format!("{} / {}", left, right) };
+                return Ok(Some(format!("{}: {}", at, diff)));
+            }
+        }
+
+        vm.finish(fini)?;
+        other.finish(other_fini)?;
+        Ok(vm.snapshot().diff(&other.snapshot()).map(|diff| format!("after main: {}", diff)))
+    }
+
+    fn current(&self) -> String {
+        match self.instructions.get(self.pc) {
+            Some(instruction) if !self.halted => format!("pc {} ({})", self.pc, instruction.assemble()),
+            _ => "end".to_string()
+        }
+    }
+
+    // Runs the instruction at pc, returning false when the program has already finished.
+    fn step(&mut self) -> Result<bool, Error> {
+        if self.halted || self.pc >= self.instructions.len() {
+            return Ok(false);
+        }
+        let pc = self.pc;
+        self.run(pc..pc + 1)?;
+        Ok(true)
+    }
+
+    pub fn execute(&mut self, program: Program) -> Result<ExitStatus, Error> {
+        let result = self.execute_program(program);
+        if let Some(mut telemetry) = self.telemetry.take() {
+            let stats = self.stats();
+            match &result {
+                Ok(_) => telemetry.finished(&stats),
+                Err(e) => telemetry.failed(e, &stats)
+            }
+            self.telemetry = Some(telemetry);
+        }
+        result
+    }
+
+    fn execute_program(&mut self, program: Program) -> Result<ExitStatus, Error> {
+        let fini = self.start(program)?;
+        if !self.halted {
+            self.run(0..self.instructions.len())?;
+        }
+        self.finish(fini)
+    }
+
+    // Loads a program and runs its #init sections, leaving pc at the entry point. Returns the #fini sections for
+    // finish to run.
+    fn start(&mut self, program: Program) -> Result<Vec<usize>, Error> {
+        let entry = program.entry_label();
+        let init = program.init.clone();
+        let fini = program.fini.clone();
+        self.exit_code = 0;
+        self.halted = false;
+        self.load(program)?;
+
+        for start in init {
+            self.run_section("#init", start)?;
+            if self.halted {
+                break;
+            }
+        }
+
+        self.pc = 0;
+        if let Some(entry) = entry {
+            self.pc = self.jump_to_label(Field::from(entry), &self.labels)?;
+        }
+        Ok(fini)
+    }
+
+    // Runs the #fini sections, which still happens after a hlt, until one of them halts too.
+    fn finish(&mut self, fini: Vec<usize>) -> Result<ExitStatus, Error> {
+        self.halted = false;
+        for start in fini {
+            self.run_section("#fini", start)?;
+            if self.halted {
+                break;
+            }
+        }
+        Ok(ExitStatus { code: self.exit_code, result: self.stack.peek().cloned() })
+    }
+
+    pub fn load(&mut self, mut program: Program) -> Result<(), Error> {
+        if let Err(message) = program.resolve_label_offsets() {
+            let err = self.error(ErrorKind::UnknownLabel, message, None);
+            return Err(err.err().unwrap());
+        }
+        if self.options.trap_fallthrough {
+            self.functions = program.functions();
+        }
+        self.strict_symbols = program.strict_symbols.into_iter().collect();
         self.instructions = program.instructions;
         self.labels = program.labels;
         self.data = program.data;
+        self.label_cache = vec![None; self.instructions.len()];
+        self.data_cache = vec![None; self.instructions.len()];
+
+        if self.options.reflection && !self.heap.contains_key(STACK_SIZE_VAR) {
+            self.allocate_heap(&Field::from(STACK_SIZE_VAR))?;
+            self.allocate_heap(&Field::from(CALLSTACK_SIZE_VAR))?;
+            self.allocate_heap(&Field::from(PC_VAR))?;
+        }
+        Ok(())
+    }
+
+    // Calls a routine in the loaded program against the current stack and heap, returning once it rets, halts or
+    // fails. Control may go anywhere in the program on the way, and the call stack is left as it was found.
+    pub fn execute_from(&mut self, label: &str) -> Result<(), Error> {
+        let start = self.jump_to_label(Field::from(label), &self.labels)?;
+        self.halted = false;
+        self.run_section(label, start)
+    }
+
+    // Runs the instructions in start..end against the current stack and heap, returning once pc reaches end or a
+    // hlt. A jump, call or ret that takes control anywhere else is an out_of_range error, and the call stack is left
+    // as it was found either way.
+    pub fn execute_range(&mut self, start: usize, end: usize) -> Result<(), Error> {
+        if start > end || end > self.instructions.len() {
+            let err = self.error(ErrorKind::OutOfRange, "Range is outside of the program.".to_string(), Some(vec![Field::from(start), Field::from(end)]));
+            return Err(err.err().unwrap());
+        }
+        let depth = self.call_stack.len();
+        self.halted = false;
+        self.pc = start;
+        let mut result = self.run(start..end);
+        if result.is_ok() && !self.halted && (self.pc != end || self.call_stack.len() != depth) {
+            let message = format!("Control left the range {}..{} for {}.", start, end, self.pc);
+            result = Err(self.error(ErrorKind::OutOfRange, message, None).err().unwrap());
+        }
+        self.unwind(depth);
+        result
+    }
 
-        let stack_size_var = Field::from("$__stack_size");
-        let callstack_size_var = Field::from("$__callstack_size");
-        let pc_var = Field::from("$__pc");
+    fn run_section(&mut self, name: &str, start: usize) -> Result<(), Error> {
+        let depth = self.call_stack.len();
+        self.call_stack.push(Frame::entered(name.to_string(), self.instructions.len(), self.stack.len()));
+        self.pc = start;
+        let result = self.run(0..self.instructions.len());
+        // A hlt or an error ends the section without returning, leaving its frame and any above it behind.
+        self.unwind(depth);
+        result
+    }
 
-        if self.reflection {
-            self.allocate_heap(&stack_size_var)?;
-            self.allocate_heap(&callstack_size_var)?;
-            self.allocate_heap(&pc_var)?;
+    fn unwind(&mut self, depth: usize) {
+        while self.call_stack.len() > depth {
+            self.call_stack.pop();
         }
+    }
+
+    fn run(&mut self, bounds: Range<usize>) -> Result<(), Error> {
+        let stack_size_var = Field::from(STACK_SIZE_VAR);
+        let callstack_size_var = Field::from(CALLSTACK_SIZE_VAR);
+        let pc_var = Field::from(PC_VAR);
 
-        while (self.pc as usize) < self.instructions.len() {
-            let tmp_ins = &self.instructions[self.pc as usize];
+        while bounds.contains(&self.pc) {
+            self.stats.instructions += 1;
+            if self.stats.instructions.is_multiple_of(CANCEL_CHECK_INTERVAL) && self.cancel.is_cancelled() {
+                return self.error(ErrorKind::Cancelled, format!("Execution was cancelled at {}.", self.pc), None);
+            }
+            if self.options.profile {
+                self.profile.record(self.call_stack.to_vec().iter().map(|f| f.label.as_str()));
+            }
+            let tmp_ins = &self.instructions[self.pc];
             let mut instruction = tmp_ins.clone();
             match instruction.opcode {
                 OpCode::Push => {
-                    let operand = self.pop_operand(&mut instruction.operand)?;
-                    let operand_as_str = operand.to_str();
-                    match operand_as_str {
-                        Some(s) => {
-                            if self.data.contains_key(s) {
-                                self.stack.push(self.data.get(s).unwrap().clone());
-                            } else {
-                                self.stack.push(operand);
+                    let value = match &self.data_cache[self.pc] {
+                        Some(value) => value.clone(),
+                        None => {
+                            let operand = self.pop_operand(&mut instruction.operand)?;
+                            let value = self.resolve_data(operand);
+                            if let (true, Some(symbol)) = (self.strict_symbols.contains(&self.pc), value.to_str()) {
+                                if symbol.starts_with('@') && !self.labels.contains_key(symbol) {
+                                    return self.error(ErrorKind::UnknownLabel, format!("Unknown symbol {} in strict mode!", symbol), Some(vec![value.clone()]));
+                                }
                             }
+                            self.data_cache[self.pc] = Some(value.clone());
+                            value
                         }
-                        None => self.stack.push(operand)
-                    }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Cmp => {
+                    let v2 = match instruction.operand.pop() {
+                        Some(operand) => self.resolve_data(operand),
+                        None => self.pop_stack()?
+                    };
+                    let v1 = match instruction.operand.pop() {
+                        Some(operand) => self.resolve_data(operand),
+                        None => self.pop_stack()?
+                    };
+                    let order = match v1.partial_cmp(&v2) {
+                        Some(cmp::Ordering::Less) => -1,
+                        Some(cmp::Ordering::Greater) => 1,
+                        _ => 0
+                    };
+                    self.stack.push(Field::I(order));
                 }
                 OpCode::Pop => {
                     self.pop_stack()?;
@@ -86,72 +435,313 @@ impl Vm {
                 OpCode::Add => {
                     let a2 = self.pop_stack()?;
                     let a1 = self.pop_stack()?;
-                    let i2 = self.check_int(a2)?;
-                    let i1 = self.check_int(a1)?;
-                    self.stack.push(Field::I(i1 + i2));
+                    let result = match (&a1, &a2) {
+                        (Field::S(_), _) | (_, Field::S(_)) => Field::from(format!("{}{}", a1, a2)),
+                        _ => {
+                            let (i1, i2) = self.check_ints(a1, a2)?;
+                            Field::I(self.overflow(i1.checked_add(i2), i1.wrapping_add(i2))?)
+                        }
+                    };
+                    self.stack.push(result);
                 }
                 OpCode::Mul => {
                     let a2 = self.pop_stack()?;
                     let a1 = self.pop_stack()?;
-                    let i2 = self.check_int(a2)?;
-                    let i1 = self.check_int(a1)?;
-                    self.stack.push(Field::I(i1 * i2));
+                    let times = match &a2 {
+                        Field::I(i) => usize::try_from(*i).ok(),
+                        Field::U(u) => Some(*u),
+                        _ => None
+                    };
+                    let result = match (&a1, times) {
+                        (Field::S(text), Some(times)) => match text.len().checked_mul(times) {
+                            Some(len) if len <= MAX_STRING_LEN => Field::from(text.repeat(times)),
+                            _ => {
+                                return self.error(ErrorKind::Overflow, format!("Cannot repeat a string past {} bytes!", MAX_STRING_LEN), Some(vec![a1, a2]));
+                            }
+                        },
+                        (Field::S(_), None) => {
+                            return self.error(ErrorKind::OutOfRange, "Cannot repeat a string a negative or non-int number of times!".to_string(), Some(vec![a1, a2]));
+                        }
+                        _ => {
+                            let (i1, i2) = self.check_ints(a1, a2)?;
+                            Field::I(self.overflow(i1.checked_mul(i2), i1.wrapping_mul(i2))?)
+                        }
+                    };
+                    self.stack.push(result);
                 }
                 OpCode::Sub => {
                     let a2 = self.pop_stack()?;
                     let a1 = self.pop_stack()?;
-                    let i2 = self.check_int(a2)?;
-                    let i1 = self.check_int(a1)?;
-                    self.stack.push(Field::I(i1 - i2));
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    let result = self.overflow(i1.checked_sub(i2), i1.wrapping_sub(i2))?;
+                    self.stack.push(Field::I(result));
+                }
+                OpCode::Adds => {
+                    let a2 = self.pop_stack()?;
+                    let a1 = self.pop_stack()?;
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    self.stack.push(Field::I(i1.saturating_add(i2)));
+                }
+                OpCode::Subs => {
+                    let a2 = self.pop_stack()?;
+                    let a1 = self.pop_stack()?;
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    self.stack.push(Field::I(i1.saturating_sub(i2)));
+                }
+                OpCode::Pow => {
+                    let a2 = self.pop_stack()?;
+                    let a1 = self.pop_stack()?;
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    let exponent = match u32::try_from(i2) {
+                        Ok(e) => e,
+                        Err(_) => {
+                            return self.error(ErrorKind::OutOfRange, format!("Cannot raise to a negative or huge power at {}!", self.pc), Some(vec![Field::I(i1), Field::I(i2)]));
+                        }
+                    };
+                    let result = self.overflow(i1.checked_pow(exponent), i1.wrapping_pow(exponent))?;
+                    self.stack.push(Field::I(result));
+                }
+                OpCode::Sqrt => {
+                    let v1 = self.pop_stack()?;
+                    match v1 {
+                        Field::I(i) if i >= 0 => self.stack.push(Field::from(i.isqrt())),
+                        Field::U(u) => self.stack.push(Field::from(u.isqrt())),
+                        Field::I(_) => {
+                            return self.error(ErrorKind::OutOfRange, format!("Cannot take the square root of a negative int at {}!", self.pc), Some(vec![v1]));
+                        }
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot take the square root of non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::Muls => {
+                    let a2 = self.pop_stack()?;
+                    let a1 = self.pop_stack()?;
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    self.stack.push(Field::I(i1.saturating_mul(i2)));
                 }
                 OpCode::Div => {
                     let a2 = self.pop_stack()?;
                     let a1 = self.pop_stack()?;
-                    let i2 = self.check_int(a2)?;
-                    let i1 = self.check_int(a1)?;
-                    self.stack.push(Field::I(i1 / i2));
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    if i2 == 0 {
+                        return self.error(ErrorKind::DivideByZero, format!("Cannot divide by zero at {}!", self.pc), Some(vec![Field::I(i1), Field::I(i2)]));
+                    }
+                    let result = self.overflow(i1.checked_div(i2), i1.wrapping_div(i2))?;
+                    self.stack.push(Field::I(result));
                 }
                 OpCode::Mod => {
                     let a2 = self.pop_stack()?;
                     let a1 = self.pop_stack()?;
-                    let i2 = self.check_int(a2)?;
-                    let i1 = self.check_int(a1)?;
-                    self.stack.push(Field::I(i1 % i2));
+                    let (i1, i2) = self.check_ints(a1, a2)?;
+                    if i2 == 0 {
+                        return self.error(ErrorKind::DivideByZero, format!("Cannot take the remainder of dividing by zero at {}!", self.pc), Some(vec![Field::I(i1), Field::I(i2)]));
+                    }
+                    let result = self.overflow(i1.checked_rem(i2), i1.wrapping_rem(i2))?;
+                    self.stack.push(Field::I(result));
                 }
                 OpCode::Print => {
-                    print!("{}", self.pop_stack()?);
-                    io::stdout().flush();
+                    let value = self.pop_stack()?;
+                    let _ = write!(self.stdout, "{}", value);
+                    let _ = self.stdout.flush();
                 }
                 OpCode::Println => {
-                    println!("{}", self.pop_stack()?);
+                    let value = self.pop_stack()?;
+                    let _ = writeln!(self.stdout, "{}", value);
                 }
                 OpCode::Input => {
-                    let input = self.get_input();
-                    self.stack.push(Field::from(input));
+                    self.check_capability(Capability::Input)?;
+                    let input = Field::from(self.get_input());
+                    let value = match instruction.operand.pop() {
+                        Some(kind) => {
+                            let kind = self.check_str(kind)?;
+                            self.parse_input(input, &kind)?
+                        }
+                        None => input
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Env => {
+                    self.check_capability(Capability::Env)?;
+                    let v1 = self.pop_stack()?;
+                    let name = self.check_str(v1)?;
+                    self.stack.push(Field::from(env::var(name).unwrap_or_default()));
+                }
+                OpCode::Args => {
+                    self.check_capability(Capability::Env)?;
+                    for arg in self.options.args.iter() {
+                        self.stack.push(Field::from(arg.as_str()));
+                    }
+                    self.stack.push(Field::from(self.options.args.len()));
+                }
+                OpCode::Cwd => {
+                    self.check_capability(Capability::Env)?;
+                    let cwd = env::current_dir().map(|p| p.display().to_string()).map_err(|e| format!("Unable to read the working directory: {}", e));
+                    let cwd = self.check_io(cwd)?;
+                    self.stack.push(Field::from(cwd));
+                }
+                OpCode::FileOpen => {
+                    self.check_capability(Capability::File)?;
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let path = self.check_str(v1)?;
+                    let mode = self.check_str(v2)?;
+                    let handle = self.files.open(&path, &mode);
+                    let handle = self.check_io(handle)?;
+                    self.stack.push(Field::from(handle));
+                }
+                OpCode::FileRead => {
+                    self.check_capability(Capability::File)?;
+                    let v1 = self.pop_stack()?;
+                    let handle = self.check_index(v1)?;
+                    let contents = self.files.read(handle);
+                    let contents = self.check_io(contents)?;
+                    self.stack.push(Field::from(contents));
+                }
+                OpCode::FileWrite => {
+                    self.check_capability(Capability::File)?;
+                    let value = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let handle = self.check_index(v1)?;
+                    let written = self.files.write(handle, &value.to_string());
+                    self.check_io(written)?;
+                }
+                OpCode::FileClose => {
+                    self.check_capability(Capability::File)?;
+                    let v1 = self.pop_stack()?;
+                    let handle = self.check_index(v1)?;
+                    let closed = self.files.close(handle);
+                    self.check_io(closed)?;
                 }
                 OpCode::Call => {
-                    self.call_stack.push(self.pc + 1);
+                    let (label, result) = match instruction.operand.pop() {
+                        Some(operand) => (operand.clone(), self.cached_jump(operand)?),
+                        None => {
+                            let operand = self.pop_stack()?;
+                            (operand.clone(), self.jump_to_label(operand, &self.labels)?)
+                        }
+                    };
+                    self.call_stack.push(Frame::new(label.to_string(), self.pc + 1, self.stack.len()));
+                    self.pc = result;
+                    continue;
+                }
+                OpCode::TailCall => {
                     let label = self.pop_operand(&mut instruction.operand)?;
-                    let result = self.jump_to_label(label, &self.labels)?;
+                    let result = self.cached_jump(label.clone())?;
+                    if let Some(frame) = self.call_stack.peek_mut() {
+                        frame.label = label.to_string();
+                        frame.locals = 0;
+                        if let Some(frame_pointer) = frame.frame_pointer.take() {
+                            self.stack.truncate(frame_pointer);
+                        }
+                    }
                     self.pc = result;
                     continue;
                 }
                 OpCode::Ret => {
-                    self.pc = self.pop_call_stack()?;
+                    let args = match instruction.operand.pop() {
+                        Some(operand) => Some((self.check_int(operand.clone())?, operand)),
+                        None => None
+                    };
+                    let frame = self.pop_call_stack()?;
+                    if let Some((n, operand)) = args {
+                        if n < 0 || n as usize > frame.stack_base || frame.stack_base > self.stack.len() {
+                            return self.error(ErrorKind::OutOfRange, "Cannot discard more arguments than were passed!".to_string(), Some(vec![operand]));
+                        }
+                        for _ in 0..n {
+                            self.stack.remove(frame.stack_base - n as usize);
+                        }
+                    }
+                    self.pc = frame.return_pc;
                     continue;
                 }
-                OpCode::Jmp => {
+                OpCode::Arg => {
                     let operand = self.pop_operand(&mut instruction.operand)?;
-                    let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                    let n = self.check_int(operand.clone())?;
+                    let base = match self.call_stack.peek() {
+                        Some(frame) => frame.stack_base,
+                        None => {
+                            return self.error(ErrorKind::OutOfRange, "Cannot read an argument outside of a call!".to_string(), Some(vec![operand]));
+                        }
+                    };
+                    if n < 0 || n as usize >= base || base > self.stack.len() {
+                        return self.error(ErrorKind::OutOfRange, "Argument is out of range for the current frame!".to_string(), Some(vec![operand]));
+                    }
+                    let value = self.stack.get(base - 1 - n as usize).unwrap().clone();
+                    self.stack.push(value);
+                }
+                OpCode::Jmp => {
+                    let result = match instruction.operand.pop() {
+                        Some(operand) => self.cached_jump(operand)?,
+                        None => {
+                            let operand = self.pop_stack()?;
+                            self.jump_to_label(operand, &self.labels)?
+                        }
+                    };
                     self.pc = result;
                     continue;
                 }
+                OpCode::Switch => {
+                    let targets = instruction.operand.to_vec().clone();
+                    let default = match targets.last() {
+                        Some(target) => target.clone(),
+                        None => {
+                            return self.error(ErrorKind::MissingOperand, "Switch requires at least a default label!".to_string(), None);
+                        }
+                    };
+                    let v1 = self.pop_stack()?;
+                    let n = self.check_int(v1)?;
+                    let target = match targets.get(n as usize) {
+                        Some(target) if n >= 0 && (n as usize) < targets.len() - 1 => target.clone(),
+                        _ => default
+                    };
+                    self.pc = self.jump_to_label(target, &self.labels)?;
+                    continue;
+                }
+                OpCode::Loop => {
+                    let v1 = self.pop_stack()?;
+                    // A counter that is already zero or negative ends the loop rather than wrapping around.
+                    let remaining = match v1 {
+                        Field::I(i) if i > 1 => Some(Field::from(i - 1)),
+                        Field::U(u) if u > 1 => Some(Field::from(u - 1)),
+                        Field::I(_) | Field::U(_) => None,
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot loop on non-int counter at {}!", self.pc), Some(vec![v1]));
+                        }
+                    };
+                    if let Some(remaining) = remaining {
+                        self.stack.push(remaining);
+                        let operand = self.pop_operand(&mut instruction.operand)?;
+                        let result = self.cached_jump(operand)?;
+                        self.pc = result;
+                        continue;
+                    }
+                }
+                OpCode::Jnull | OpCode::Jnotnull => {
+                    let operand = self.pop_operand(&mut instruction.operand)?;
+                    let var = self.pop_operand(&mut instruction.operand)?;
+                    let key = self.check_str(var)?;
+                    let null = !matches!(self.heap.get(&key), Some(Heap { item: Some(_) }));
+                    if null == (instruction.opcode == OpCode::Jnull) {
+                        let result = self.cached_jump(operand)?;
+                        self.pc = result;
+                        continue;
+                    }
+                }
+                OpCode::Jz | OpCode::Jnz => {
+                    if self.zero == (instruction.opcode == OpCode::Jz) {
+                        let operand = self.pop_operand(&mut instruction.operand)?;
+                        let result = self.cached_jump(operand)?;
+                        self.pc = result;
+                        continue;
+                    }
+                }
                 OpCode::Je => {
                     let v2 = self.pop_stack()?;
                     let v1 = self.pop_stack()?;
                     if v1 == v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -161,7 +751,27 @@ impl Vm {
                     let v1 = self.pop_stack()?;
                     if v1 != v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
+                        self.pc = result;
+                        continue;
+                    }
+                }
+                OpCode::Ja => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    if v1.unsigned_cmp(&v2) == Some(cmp::Ordering::Greater) {
+                        let operand = self.pop_operand(&mut instruction.operand)?;
+                        let result = self.cached_jump(operand)?;
+                        self.pc = result;
+                        continue;
+                    }
+                }
+                OpCode::Jb => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    if v1.unsigned_cmp(&v2) == Some(cmp::Ordering::Less) {
+                        let operand = self.pop_operand(&mut instruction.operand)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -171,7 +781,7 @@ impl Vm {
                     let v1 = self.pop_stack()?;
                     if v1 < v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -181,7 +791,7 @@ impl Vm {
                     let v1 = self.pop_stack()?;
                     if v1 > v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -191,7 +801,7 @@ impl Vm {
                     let v1 = self.pop_stack()?;
                     if v1 <= v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -201,7 +811,7 @@ impl Vm {
                     let v1 = self.pop_stack()?;
                     if v1 >= v2 {
                         let operand = self.pop_operand(&mut instruction.operand)?;
-                        let result = self.jump_to_label(operand.clone(), &self.labels)?;
+                        let result = self.cached_jump(operand)?;
                         self.pc = result;
                         continue;
                     }
@@ -209,32 +819,164 @@ impl Vm {
                 OpCode::Inc => {
                     let v1 = self.pop_stack()?;
                     match v1 {
-                        Field::I(mut i) => {
-                            i += 1;
-                            self.stack.push(Field::from(i));
+                        Field::I(i) => {
+                            let result = self.overflow(i.checked_add(1), i.wrapping_add(1))?;
+                            self.stack.push(Field::from(result));
                         }
-                        Field::U(mut u) => {
-                            u += 1;
-                            self.stack.push(Field::from(u));
+                        Field::U(u) => {
+                            let result = self.overflow(u.checked_add(1), u.wrapping_add(1))?;
+                            self.stack.push(Field::from(result));
                         }
                         _ => {
-                            return self.error(format!("Cannot increment non-int type at {}!", self.pc), Some(vec![v1]));
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot increment non-int type at {}!", self.pc), Some(vec![v1]));
                         }
                     }
                 }
                 OpCode::Dec => {
                     let v1 = self.pop_stack()?;
                     match v1 {
-                        Field::I(mut i) => {
-                            i -= 1;
-                            self.stack.push(Field::from(i));
+                        Field::I(i) => {
+                            let result = self.overflow(i.checked_sub(1), i.wrapping_sub(1))?;
+                            self.stack.push(Field::from(result));
+                        }
+                        Field::U(u) => {
+                            let result = self.overflow(u.checked_sub(1), u.wrapping_sub(1))?;
+                            self.stack.push(Field::from(result));
+                        }
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot decrement non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::Abs => {
+                    let v1 = self.pop_stack()?;
+                    match v1 {
+                        Field::I(i) => {
+                            let result = self.overflow(i.checked_abs(), i.wrapping_abs())?;
+                            self.stack.push(Field::from(result));
+                        }
+                        Field::U(u) => self.stack.push(Field::from(u)),
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot take absolute value of non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::Neg => {
+                    let v1 = self.pop_stack()?;
+                    match v1 {
+                        Field::I(i) => {
+                            let result = self.overflow(i.checked_neg(), i.wrapping_neg())?;
+                            self.stack.push(Field::from(result));
+                        }
+                        Field::U(u) => {
+                            let result = self.overflow(u.checked_neg(), u.wrapping_neg())?;
+                            self.stack.push(Field::from(result));
+                        }
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot negate non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::Sign => {
+                    let v1 = self.pop_stack()?;
+                    match v1 {
+                        Field::I(i) => self.stack.push(Field::from(i.signum())),
+                        Field::U(u) => self.stack.push(Field::from(cmp::min(u, 1))),
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot take sign of non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::Popcnt | OpCode::Clz | OpCode::Ctz => {
+                    let v1 = self.pop_stack()?;
+                    let count = match v1 {
+                        Field::I(i) => match instruction.opcode {
+                            OpCode::Popcnt => i.count_ones(),
+                            OpCode::Clz => i.leading_zeros(),
+                            _ => i.trailing_zeros()
+                        },
+                        Field::U(u) => match instruction.opcode {
+                            OpCode::Popcnt => u.count_ones(),
+                            OpCode::Clz => u.leading_zeros(),
+                            _ => u.trailing_zeros()
+                        },
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot count bits of non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    };
+                    self.stack.push(Field::from(count as i64));
+                }
+                OpCode::Bt | OpCode::Bts | OpCode::Btr | OpCode::Btc => {
+                    let n = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let bit = self.check_bit_index(n)?;
+                    match v1 {
+                        Field::I(i) => {
+                            let mask = 1i64 << bit;
+                            self.stack.push(Field::from(match instruction.opcode {
+                                OpCode::Bt => ((i & mask) != 0) as i64,
+                                OpCode::Bts => i | mask,
+                                OpCode::Btr => i & !mask,
+                                _ => i ^ mask
+                            }));
+                        }
+                        Field::U(u) => {
+                            let mask = 1usize << bit;
+                            self.stack.push(Field::from(match instruction.opcode {
+                                OpCode::Bt => ((u & mask) != 0) as usize,
+                                OpCode::Bts => u | mask,
+                                OpCode::Btr => u & !mask,
+                                _ => u ^ mask
+                            }));
+                        }
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot test bits of non-int type at {}!", self.pc), Some(vec![v1]));
+                        }
+                    }
+                }
+                OpCode::And | OpCode::Or => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    match (&v1, &v2) {
+                        (Field::I(i1), Field::I(i2)) => self.stack.push(Field::from(match instruction.opcode {
+                            OpCode::And => i1 & i2,
+                            _ => i1 | i2
+                        })),
+                        (Field::U(u1), Field::U(u2)) => self.stack.push(Field::from(match instruction.opcode {
+                            OpCode::And => u1 & u2,
+                            _ => u1 | u2
+                        })),
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot combine bits of mismatched or non-int types at {}!", self.pc), Some(vec![v1, v2]));
                         }
-                        Field::U(mut u) => {
-                            u -= 1;
-                            self.stack.push(Field::from(u));
+                    }
+                }
+                OpCode::Not => {
+                    let v1 = self.pop_stack()?;
+                    match v1 {
+                        Field::I(i) => self.stack.push(Field::from(!i)),
+                        Field::U(u) => self.stack.push(Field::from(!u)),
+                        _ => {
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot invert non-int type at {}!", self.pc), Some(vec![v1]));
                         }
+                    }
+                }
+                OpCode::Shl | OpCode::Shr | OpCode::Sar => {
+                    let n = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let bits = self.check_bit_index(n)?;
+                    match v1 {
+                        Field::I(i) => self.stack.push(Field::from(match instruction.opcode {
+                            OpCode::Shl => i << bits,
+                            OpCode::Shr => ((i as u64) >> bits) as i64,
+                            _ => i >> bits
+                        })),
+                        Field::U(u) => self.stack.push(Field::from(match instruction.opcode {
+                            OpCode::Shl => u << bits,
+                            _ => u >> bits
+                        })),
                         _ => {
-                            return self.error(format!("Cannot decrement non-int type at {}!", self.pc), Some(vec![v1]));
+                            return self.error(ErrorKind::TypeMismatch, format!("Cannot shift non-int type at {}!", self.pc), Some(vec![v1]));
                         }
                     }
                 }
@@ -248,7 +990,7 @@ impl Vm {
                     let v2 = self.pop_stack()?;
                     let v1 = self.pop_stack()?;
 
-                    self.stack.push(Field::from(format!("{}{}", v1.to_string(),v2.to_string())));
+                    self.stack.push(Field::from(format!("{}{}", v1, v2)));
                 }
                 OpCode::Swap => {
                     let v2 = self.pop_stack()?;
@@ -262,6 +1004,32 @@ impl Vm {
 
                     self.allocate_heap(&address)?;
                 }
+                OpCode::ArenaBegin => {
+                    self.arenas.push(vec![]);
+                }
+                OpCode::ArenaEnd => {
+                    let arena = match self.arenas.pop() {
+                        Some(a) => a,
+                        None => {
+                            return self.error(ErrorKind::Heap, "There is no arena to end!".to_string(), None);
+                        }
+                    };
+                    for var in arena {
+                        self.remove_heap(&var);
+                    }
+                }
+                OpCode::HeapMark => {
+                    self.heap_mark();
+                }
+                OpCode::HeapDiff => {
+                    let diff = match self.heap_diff() {
+                        Some(diff) => diff,
+                        None => {
+                            return self.error(ErrorKind::Heap, "heap_diff needs a heap_mark first!".to_string(), None);
+                        }
+                    };
+                    self.stack.push(Field::from(diff.to_string()));
+                }
                 OpCode::Free => {
                     let address = self.pop_operand(&mut instruction.operand)?;
 
@@ -270,26 +1038,266 @@ impl Vm {
                 OpCode::Load => {
                     let address = self.pop_operand(&mut instruction.operand)?;
 
-                    let heap_copy = self.load_heap(&address)?;
-                    self.stack.push(heap_copy);
-
+                    if let Field::I(_) | Field::U(_) = address {
+                        let index = self.stack_slot(&address)?;
+                        let value = self.stack.get(index).unwrap().clone();
+                        self.stack.push(value);
+                    } else {
+                        let heap_copy = self.load_heap(&address)?;
+                        self.stack.push(heap_copy);
+                    }
                 }
                 OpCode::Store => {
                     let address = self.pop_operand(&mut instruction.operand)?;
                     let v1 = self.pop_stack()?;
 
-                    self.store_heap(&address, v1)?;
+                    if let Field::I(_) | Field::U(_) = address {
+                        let index = self.stack_slot(&address)?;
+                        *self.stack.get_mut(index).unwrap() = v1;
+                    } else {
+                        self.store_heap(&address, v1)?;
+                    }
                 }
-                OpCode::Nop => (),
-                OpCode::Hlt => {
-                    return Ok(());
+                OpCode::Xchg => {
+                    let b = self.pop_operand(&mut instruction.operand)?;
+                    let a = self.pop_operand(&mut instruction.operand)?;
+
+                    self.exchange_heap(&a, &b)?;
                 }
-                OpCode::Igl => {
-                    return self.error(format!("ILLEGAL instruction encountered at {}.", self.pc), None);
+                OpCode::Enter => {
+                    let operand = self.pop_operand(&mut instruction.operand)?;
+                    let n = self.check_int(operand.clone())?;
+                    if n < 0 {
+                        return self.error(ErrorKind::OutOfRange, "Cannot enter a frame with a negative number of locals!".to_string(), Some(vec![operand]));
+                    }
+                    if n > MAX_LOCALS {
+                        return self.error(ErrorKind::OutOfRange, format!("Cannot enter a frame with more than {} locals!", MAX_LOCALS), Some(vec![operand]));
+                    }
+                    let frame_pointer = self.stack.len();
+                    let frame = self.current_frame()?;
+                    if frame.frame_pointer.is_some() {
+                        return self.error(ErrorKind::IllegalInstruction, "Frame has already been entered!".to_string(), None);
+                    }
+                    frame.frame_pointer = Some(frame_pointer);
+                    frame.locals = n as usize;
+                    for _ in 0..n {
+                        self.stack.push(Field::I(0));
+                    }
                 }
+                OpCode::Leave => {
+                    let frame = self.current_frame()?;
+                    match frame.frame_pointer.take() {
+                        Some(frame_pointer) => {
+                            frame.locals = 0;
+                            self.stack.truncate(frame_pointer);
+                        }
+                        None => {
+                            return self.error(ErrorKind::IllegalInstruction, "Cannot leave a frame that was never entered!".to_string(), None);
+                        }
+                    }
+                }
+                OpCode::LocalGet => {
+                    let operand = self.pop_operand(&mut instruction.operand)?;
+                    let slot = self.local_slot(operand)?;
+                    let value = self.stack.get(slot).unwrap().clone();
+                    self.stack.push(value);
+                }
+                OpCode::LocalSet => {
+                    let operand = self.pop_operand(&mut instruction.operand)?;
+                    let slot = self.local_slot(operand)?;
+                    let value = self.pop_stack()?;
+                    *self.stack.get_mut(slot).unwrap() = value;
+                }
+                OpCode::Rand => {
+                    let a2 = self.pop_stack()?;
+                    let a1 = self.pop_stack()?;
+                    let (min, max) = self.check_ints(a1, a2)?;
+                    if min > max {
+                        return self.error(ErrorKind::OutOfRange, format!("Random range is empty at {}!", self.pc), Some(vec![Field::I(min), Field::I(max)]));
+                    }
+                    let value = self.rng.range(min, max);
+                    self.stack.push(Field::I(value));
+                }
+                OpCode::Seed => {
+                    let v1 = self.pop_stack()?;
+                    let seed = self.check_int(v1)?;
+                    self.rng = Rng::new(seed as u64);
+                }
+                OpCode::Strlen => {
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    self.stack.push(Field::from(s.chars().count()));
+                }
+                OpCode::Substr => {
+                    let len = self.pop_stack()?;
+                    let start = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let start = self.check_index(start)?;
+                    let len = self.check_index(len)?;
+                    let count = s.chars().count();
+                    if start.checked_add(len).is_none_or(|end| end > count) {
+                        return self.error(ErrorKind::OutOfRange, format!("Substring runs past the end of the string at {}!", self.pc), Some(vec![Field::from(start), Field::from(len)]));
+                    }
+                    self.stack.push(Field::from(s.chars().skip(start).take(len).collect::<String>()));
+                }
+                OpCode::CharAt => {
+                    let index = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let index = self.check_index(index)?;
+                    match s.chars().nth(index) {
+                        Some(c) => self.stack.push(Field::from(c.to_string())),
+                        None => {
+                            return self.error(ErrorKind::OutOfRange, format!("Character index is past the end of the string at {}!", self.pc), Some(vec![Field::from(index)]));
+                        }
+                    }
+                }
+                OpCode::IndexOf => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let needle = self.check_str(v2)?;
+                    let index = match s.find(&needle) {
+                        Some(byte) => s[..byte].chars().count() as i64,
+                        None => -1
+                    };
+                    self.stack.push(Field::I(index));
+                }
+                OpCode::Upper | OpCode::Lower | OpCode::Trim => {
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let result = match instruction.opcode {
+                        OpCode::Upper => s.to_uppercase(),
+                        OpCode::Lower => s.to_lowercase(),
+                        _ => s.trim().to_string()
+                    };
+                    self.stack.push(Field::from(result));
+                }
+                OpCode::Replace => {
+                    let v3 = self.pop_stack()?;
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let from = self.check_str(v2)?;
+                    let to = self.check_str(v3)?;
+                    if from.is_empty() {
+                        return self.error(ErrorKind::OutOfRange, format!("Cannot replace an empty string at {}!", self.pc), None);
+                    }
+                    self.stack.push(Field::from(s.replace(&from, &to)));
+                }
+                OpCode::Assert => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    if v1 != v2 {
+                        let mut msg = format!("Assertion failed at {}:\n  left:  {}\n  right: {}", self.pc, describe(&v1), describe(&v2));
+                        if let (Field::S(left), Field::S(right)) = (&v1, &v2) {
+                            if left.contains('\n') || right.contains('\n') {
+                                msg.push_str(&format!("\n--- left\n+++ right\n{}", diff::lines(left, right).trim_end()));
+                            }
+                        }
+                        return self.error(ErrorKind::Assertion, msg, Some(vec![v1, v2]));
+                    }
+                }
+                OpCode::Format => {
+                    let v1 = self.pop_stack()?;
+                    let format = self.check_str(v1)?;
+                    let count = format.replace("{{", "").matches("{}").count();
+                    if count > self.stack.len() {
+                        return self.error(ErrorKind::StackUnderflow, format!("Format string needs {} values but the stack has {} at {}!", count, self.stack.len(), self.pc), Some(vec![Field::from(format)]));
+                    }
+                    let mut args = vec![];
+                    for _ in 0..count {
+                        args.push(self.pop_stack()?);
+                    }
+                    let mut result = String::new();
+                    let mut chars = format.chars().peekable();
+                    while let Some(c) = chars.next() {
+                        match (c, chars.peek()) {
+                            ('{', Some('{')) | ('}', Some('}')) => {
+                                chars.next();
+                                result.push(c);
+                            }
+                            ('{', Some('}')) => {
+                                chars.next();
+                                result.push_str(&args.pop().unwrap().to_string());
+                            }
+                            _ => result.push(c)
+                        }
+                    }
+                    self.stack.push(Field::from(result));
+                }
+                OpCode::Split => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let s = self.check_str(v1)?;
+                    let delimiter = self.check_str(v2)?;
+                    if delimiter.is_empty() {
+                        return self.error(ErrorKind::OutOfRange, format!("Cannot split on an empty delimiter at {}!", self.pc), None);
+                    }
+                    let mut count: usize = 0;
+                    for part in s.split(delimiter.as_str()) {
+                        self.stack.push(Field::from(part));
+                        count += 1;
+                    }
+                    self.stack.push(Field::from(count));
+                }
+                OpCode::Join => {
+                    let v2 = self.pop_stack()?;
+                    let v1 = self.pop_stack()?;
+                    let delimiter = self.check_str(v2)?;
+                    let count = self.check_index(v1)?;
+                    if count > self.stack.len() {
+                        return self.error(ErrorKind::StackUnderflow, format!("Cannot join {} values from a stack of {} at {}!", count, self.stack.len(), self.pc), None);
+                    }
+                    let mut parts = vec![String::new(); count];
+                    for part in parts.iter_mut().rev() {
+                        let value = self.pop_stack()?;
+                        *part = value.to_string();
+                    }
+                    self.stack.push(Field::from(parts.join(&delimiter)));
+                }
+                OpCode::Cast => {
+                    let operand = self.pop_operand(&mut instruction.operand)?;
+                    let target = self.check_str(operand)?;
+                    let value = self.pop_stack()?;
+                    let cast = self.cast(value, &target)?;
+                    self.stack.push(cast);
+                }
+                OpCode::Sleep => {
+                    let v1 = self.pop_stack()?;
+                    let ms = self.check_index(v1)?;
+                    let deadline = Instant::now() + Duration::from_millis(ms as u64);
+                    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                        if self.cancel.is_cancelled() {
+                            return self.error(ErrorKind::Cancelled, format!("Execution was cancelled while sleeping at {}.", self.pc), None);
+                        }
+                        if remaining.is_zero() {
+                            break;
+                        }
+                        thread::sleep(remaining.min(SLEEP_SLICE));
+                    }
+                }
+                OpCode::Nop => (),
+                OpCode::Hlt => {
+                    if let Some(code) = instruction.operand.pop() {
+                        self.exit_code = self.check_int(code)?;
+                    }
+                    self.halted = true;
+                    return Ok(());
+                }
+                OpCode::Igl => {
+                    return self.error(ErrorKind::IllegalInstruction, format!("ILLEGAL instruction encountered at {}.", self.pc), None);
+                }
+            }
+            if instruction.opcode.sets_zero_flag() {
+                self.zero = matches!(self.stack.peek(), Some(Field::I(0)) | Some(Field::U(0)));
+            }
+            if let Some(function) = self.functions.get(&(self.pc + 1)) {
+                return self.error(ErrorKind::Fallthrough, format!("Execution fell through into function {} without a call.", function), None);
             }
             self.pc += 1;
-            if self.reflection {
+            if self.options.reflection {
                 self.store_heap(&stack_size_var, Field::from(self.stack.len()))?;
                 self.store_heap(&callstack_size_var, Field::from(self.call_stack.len()))?;
                 self.store_heap(&pc_var, Field::from(self.pc))?;
@@ -298,55 +1306,122 @@ impl Vm {
         Ok(())
     }
 
-    fn error(&self, msg: String, field: Option<Vec<Field>>) -> Result<(),Error> {
-        let first_instruction = cmp::max(self.pc as i32 - 4, 0) as usize;
-        let last_instruction = cmp::min(self.pc + 4, self.instructions.len());
+    fn error(&self, kind: ErrorKind, msg: String, field: Option<Vec<Field>>) -> Result<(),Error> {
+        let first_instruction = self.pc.saturating_sub(self.options.error_window);
+        let last_instruction = cmp::min(self.pc + self.options.error_window + 1, self.instructions.len());
         let mut stack: Vec<String> = Vec::new();
         for i in first_instruction..last_instruction {
             let mut assembled = self.instructions[i].assemble();
             if i == self.pc {
                 match &field {
                     Some(f) => {
-                        assembled.push_str(format!(" <-- error occurred here, operand(s): ").as_str());
+                        assembled.push_str(" <-- error occurred here, operand(s): ");
                         for item in f {
-                            assembled.push_str(format!("{} ", item.to_string()).as_str());
+                            assembled.push_str(format!("{} ", item).as_str());
                         }
                     },
                     None => {
                         assembled.push_str(" <-- error occurred here");
                     }
                 }
-
+                let resolved = self.resolve_operands(&self.instructions[i]);
+                if !resolved.is_empty() {
+                    assembled.push_str(format!(" (resolved: {})", resolved.join(", ")).as_str());
+                }
+                if self.options.error_color {
+                    assembled = format!("\x1b[1;31m{}\x1b[0m", assembled);
+                }
             }
             stack.push(format!("{}\t | {}", i, assembled));
         }
-        let app_stack = self.stack.to_vec().clone();
+        for frame in self.call_stack.to_vec().iter().rev() {
+            match frame.call_pc.and_then(|pc| self.instructions.get(pc).map(|i| (pc, i))) {
+                Some((call_pc, call)) => stack.push(format!("{}\t | {} <-- called {} from here, frame base {}", call_pc, call.assemble(), frame.label, frame.stack_base)),
+                None => stack.push(format!("\t | entered {}, frame base {}", frame.label, frame.stack_base))
+            }
+        }
         let mut new_app_stack: Vec<String> = Vec::new();
-        for i in 0..app_stack.len() {
-            new_app_stack.push(format!("{}\t: {}", i, app_stack[i]))
+        for (i, item) in self.stack.to_vec().iter().enumerate() {
+            new_app_stack.push(format!("{}\t: {}", i, item))
+        }
+        let mut error = Error::new(kind, msg, stack, new_app_stack);
+        error.pc = self.pc;
+        error.line = self.instructions.get(self.pc).and_then(|i| i.line);
+        error.frames = self.call_stack.to_vec().iter().rev().cloned().collect();
+        error.stack = self.stack.to_vec().clone();
+        Err(error)
+    }
+
+    fn resolve_data(&self, operand: Field) -> Field {
+        match operand.to_str().and_then(|s| self.data.get(s)) {
+            Some(value) => value.clone(),
+            None => operand
+        }
+    }
+
+    fn resolve_operands(&self, instruction: &Instruction) -> Vec<String> {
+        let mut resolved: Vec<String> = Vec::new();
+        for operand in instruction.operand.to_vec() {
+            let name = match operand.to_str() {
+                Some(s) => s,
+                None => continue
+            };
+            if let Some(value) = self.data.get(name) {
+                resolved.push(format!("{} = {}", name, value));
+            } else if let Some(Some(value)) = self.heap.get(name).map(|h| h.item.as_ref()) {
+                resolved.push(format!("{} = {}", name, value));
+            }
         }
-        Err(Error::new(msg, stack, new_app_stack))
+        resolved
     }
 
     fn jump_to_label(&self, operand: Field, labels: &HashMap<String,usize>) -> Result<usize, Error> {
+        if let Field::I(_) | Field::U(_) = operand {
+            return self.jump_to_index(operand);
+        }
         let label = self.check_str(operand)?;
         let new_pc = labels.get(&label);
-        return match new_pc {
+        match new_pc {
             Some(n) => {
                 Ok(*n)
             },
             None => {
-                Err(Error::new("Cannot find label.".to_string(), vec![], vec![]))
+                let err = self.error(ErrorKind::UnknownLabel, "Cannot find label.".to_string(), Some(vec![Field::from(label)]));
+                Err(err.err().unwrap())
             }
         }
     }
 
+    // Labels and data never change while a program runs, so the target of an instruction's own operand only needs
+    // to be looked up the first time that instruction executes.
+    fn cached_jump(&mut self, operand: Field) -> Result<usize, Error> {
+        if let Some(pc) = self.label_cache[self.pc] {
+            return Ok(pc);
+        }
+        let pc = self.jump_to_label(operand, &self.labels)?;
+        self.label_cache[self.pc] = Some(pc);
+        Ok(pc)
+    }
+
+    fn jump_to_index(&self, operand: Field) -> Result<usize, Error> {
+        let index = match operand {
+            Field::I(i) if i >= 0 => i as usize,
+            Field::U(u) => u,
+            _ => usize::MAX
+        };
+        if index > self.instructions.len() {
+            let err = self.error(ErrorKind::OutOfRange, "Jump target is outside of the program.".to_string(), Some(vec![operand]));
+            return Err(err.err().unwrap());
+        }
+        Ok(index)
+    }
+
     fn pop_operand(&mut self, operand: &mut Stack<Field>) -> Result<Field, Error> {
         let item = operand.pop();
         match item {
             Some(i) => Ok(i),
             None => {
-                let err = self.error("Cannot pop empty operand stack.".to_string(), None);
+                let err = self.error(ErrorKind::MissingOperand, "Cannot pop empty operand stack.".to_string(), None);
                 Err(err.err().unwrap())
             }
         }
@@ -357,18 +1432,39 @@ impl Vm {
         match item {
             Some(i) => Ok(i),
             None => {
-                let err = self.error("Cannot pop empty stack.".to_string(), None);
+                let err = self.error(ErrorKind::StackUnderflow, "Cannot pop empty stack.".to_string(), None);
                 Err(err.err().unwrap())
             }
         }
     }
 
-    fn pop_call_stack(&mut self) -> Result<usize, Error> {
+    fn pop_call_stack(&mut self) -> Result<Frame, Error> {
         let item = self.call_stack.pop();
         match item {
-            Some(u) => Ok(u),
+            Some(f) => Ok(f),
             None => {
-                let err = self.error("Cannot pop empty call stack.".to_string(), None);
+                let err = self.error(ErrorKind::StackUnderflow, "Cannot pop empty call stack.".to_string(), None);
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn current_frame(&mut self) -> Result<&mut Frame, Error> {
+        if self.call_stack.is_empty() {
+            let err = self.error(ErrorKind::OutOfRange, "Cannot use a frame outside of a call!".to_string(), None);
+            return Err(err.err().unwrap());
+        }
+        Ok(self.call_stack.peek_mut().unwrap())
+    }
+
+    fn local_slot(&mut self, operand: Field) -> Result<usize, Error> {
+        let index = self.check_int(operand.clone())?;
+        let frame = self.current_frame()?;
+        let (frame_pointer, locals) = (frame.frame_pointer, frame.locals);
+        match frame_pointer {
+            Some(fp) if index >= 0 && (index as usize) < locals && fp + (index as usize) < self.stack.len() => Ok(fp + index as usize),
+            _ => {
+                let err = self.error(ErrorKind::OutOfRange, "Local is out of range for the current frame!".to_string(), Some(vec![operand]));
                 Err(err.err().unwrap())
             }
         }
@@ -377,45 +1473,70 @@ impl Vm {
     fn allocate_heap(&mut self, var: &Field) -> Result<(), Error> {
         let cloned_field = var.clone();
         if self.heap.contains_key(self.check_str(cloned_field)?.as_str()) {
-            return self.error("That variable was already allocated!".to_string(), Some(vec![var.clone()]));
+            return self.error(ErrorKind::Heap, "That variable was already allocated!".to_string(), Some(vec![var.clone()]));
+        }
+        // The reflection variables are the VM's own bookkeeping, so they don't count against the program's heap.
+        let name = var.to_string();
+        if let Some(size) = self.options.heap_size.filter(|_| !name.starts_with(REFLECTION_PREFIX)) {
+            if self.heap.keys().filter(|k| !k.starts_with(REFLECTION_PREFIX)).count() >= size {
+                return self.error(ErrorKind::Heap, format!("Heap is full, only {} variables can be allocated!", size), Some(vec![var.clone()]));
+            }
+        }
+        let heap = Heap::new();
+        let size = heap.size(&var.to_string());
+        self.heap.insert(var.to_string(), heap);
+        self.track_heap(0, size);
+        if let Some(arena) = self.arenas.peek_mut() {
+            arena.push(var.to_string());
         }
-        self.heap.insert(var.to_string(), Heap::new());
 
         Ok(())
     }
 
     fn free_heap(&mut self, var: &Field) -> Result<(), Error> {
-        let cloned_var = var.clone();
-        let field = cloned_var.to_str().unwrap();
-        if !self.heap.contains_key(field) {
-            return self.error("The variable wasn't allocated!".to_string(), Some(vec![var.clone()]));
+        let field = self.check_str(var.clone())?;
+        if !self.heap.contains_key(field.as_str()) {
+            return self.error(ErrorKind::Heap, "The variable wasn't allocated!".to_string(), Some(vec![var.clone()]));
         }
-        self.heap.remove(field);
+        self.remove_heap(&field);
         Ok(())
     }
 
+    fn remove_heap(&mut self, var: &str) {
+        if let Some(heap) = self.heap.remove(var) {
+            self.track_heap(heap.size(var), 0);
+        }
+    }
+
+    fn track_heap(&mut self, before: usize, after: usize) {
+        self.heap_bytes = self.heap_bytes - before + after;
+        self.stats.peak_heap_bytes = self.stats.peak_heap_bytes.max(self.heap_bytes);
+        self.stats.peak_heap_entries = self.stats.peak_heap_entries.max(self.heap.len());
+    }
+
     fn load_heap(&mut self, var: &Field) -> Result<Field, Error> {
-        let key = var.to_str().unwrap();
+        let key = self.check_str(var.clone())?;
+        let key = key.as_str();
         if !self.heap.contains_key(key) {
-            let err = self.error("The variable doesn't exist!".to_string(), Some(vec![var.clone()]));
+            let err = self.error(ErrorKind::Heap, "The variable doesn't exist!".to_string(), Some(vec![var.clone()]));
             return Err(err.err().unwrap());
         }
 
         let value = self.heap.get_mut(key);
-        return match value {
+        match value {
             Some(v) => {
                 let cloned_item = v.item.clone();
-                return match cloned_item {
+                match cloned_item {
                     Some(i) => Ok(*i),
                     None => {
-                        let err = self.error("Unable to load from heap!".to_string(), Some(vec![var.clone()]));
+                        let err = self.error(ErrorKind::Heap, "Unable to load from heap!".to_string(), Some(vec![var.clone()]));
                         Err(err.err().unwrap())
                     }
                 }
 
             }
             None => {
-                let err = self.error("Unable to load from heap!".to_string(), Some(vec![var.clone()]));
+                let err = self.error(ErrorKind::Heap, "Unable to load from heap!".to_string(), Some(vec![var.clone()]));
                 Err(err.err().unwrap())
             }
         }
@@ -423,17 +1544,115 @@ impl Vm {
     }
 
     fn store_heap(&mut self, var: &Field, item: Field) -> Result<(), Error> {
-        let key = var.to_str().unwrap();
+        let key = self.check_str(var.clone())?;
+        let key = key.as_str();
         if !self.heap.contains_key(key) {
-            return self.error("The variable does not exist!".to_string(), Some(vec![var.clone()]));
+            return self.error(ErrorKind::Heap, "The variable does not exist!".to_string(), Some(vec![var.clone()]));
         }
         let item = Box::new(item);
 
         let heap = self.heap.get_mut(key);
-        let mut heapitem = heap.unwrap();
+        let heapitem = heap.unwrap();
+        let before = heapitem.size(key);
         heapitem.item = Some(item);
+        let after = heapitem.size(key);
+        self.track_heap(before, after);
+
+        Ok(())
+    }
+
+    fn stack_slot(&self, address: &Field) -> Result<usize, Error> {
+        let depth = match address {
+            Field::U(u) => Some(*u),
+            _ => usize::try_from(self.check_int(address.clone())?).ok()
+        };
+        match depth {
+            Some(depth) if depth < self.stack.len() => Ok(self.stack.len() - 1 - depth),
+            _ => {
+                let err = self.error(ErrorKind::OutOfRange, "Stack slot is out of range!".to_string(), Some(vec![address.clone()]));
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn exchange_heap(&mut self, a: &Field, b: &Field) -> Result<(), Error> {
+        for var in [a, b] {
+            if !self.heap.contains_key(self.check_str(var.clone())?.as_str()) {
+                return self.error(ErrorKind::Heap, "The variable does not exist!".to_string(), Some(vec![var.clone()]));
+            }
+        }
+        let item_a = self.heap.get_mut(&a.to_string()).unwrap().item.take();
+        let item_b = self.heap.get_mut(&b.to_string()).unwrap().item.take();
+        self.heap.get_mut(&a.to_string()).unwrap().item = item_b;
+        self.heap.get_mut(&b.to_string()).unwrap().item = item_a;
+
+        Ok(())
+    }
+
+    fn cast(&self, value: Field, target: &str) -> Result<Field, Error> {
+        let cast = match (target, &value) {
+            ("int", Field::I(i)) => Some(Field::I(*i)),
+            ("int", Field::U(u)) => i64::try_from(*u).ok().map(Field::I),
+            ("int", Field::S(s)) => s.trim().parse::<i64>().ok().map(Field::I),
+            ("uint", Field::I(i)) => usize::try_from(*i).ok().map(Field::U),
+            ("uint", Field::U(u)) => Some(Field::U(*u)),
+            ("uint", Field::S(s)) => s.trim().parse::<usize>().ok().map(Field::U),
+            ("str", Field::S(_)) => Some(value.clone()),
+            ("str", _) => Some(Field::from(value.to_string())),
+            _ => {
+                let err = self.error(ErrorKind::TypeMismatch, format!("Unknown cast type {}!", target), None);
+                return Err(err.err().unwrap());
+            }
+        };
+        match cast {
+            Some(field) => Ok(field),
+            None => {
+                let err = self.error(ErrorKind::TypeMismatch, format!("Cannot cast value to {}!", target), Some(vec![value]));
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn parse_input(&self, input: Field, kind: &str) -> Result<Field, Error> {
+        if kind != "char" {
+            return self.cast(input, kind);
+        }
+        match input.to_str().and_then(|s| s.chars().next()) {
+            Some(c) => Ok(Field::from(c.to_string())),
+            None => {
+                let err = self.error(ErrorKind::TypeMismatch, "Expected a character but the line was empty!".to_string(), None);
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn overflow<T>(&self, checked: Option<T>, wrapped: T) -> Result<T, Error> {
+        match checked {
+            Some(result) => Ok(result),
+            None if !self.options.trap_on_overflow => Ok(wrapped),
+            None => {
+                let err = self.error(ErrorKind::Overflow, format!("Arithmetic overflow at {}!", self.pc), None);
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn check_capability(&self, capability: Capability) -> Result<(), Error> {
+        if !self.options.capabilities.contains(&capability) {
+            let err = self.error(ErrorKind::Capability, format!("Capability {:?} is not granted!", capability), None);
+            return Err(err.err().unwrap());
+        }
+        Ok(())
+    }
 
-        return Ok(());
+    fn check_io<T>(&self, result: Result<T, String>) -> Result<T, Error> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(msg) => {
+                let err = self.error(ErrorKind::Io, msg, None);
+                Err(err.err().unwrap())
+            }
+        }
     }
 
     fn check_int(&self, operand: Field) -> Result<i64, Error> {
@@ -441,37 +1660,71 @@ impl Vm {
         match item {
             Some(i) => Ok(i),
             None => {
-                let err = self.error("Cannot parse as integer!".to_string(), Some(vec![operand]));
+                let err = self.error(ErrorKind::TypeMismatch, "Cannot parse as integer!".to_string(), Some(vec![operand]));
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn check_ints(&self, a1: Field, a2: Field) -> Result<(i64, i64), Error> {
+        match (a1.to_i(), a2.to_i()) {
+            (Some(i1), Some(i2)) => Ok((i1, i2)),
+            _ => {
+                let err = self.error(ErrorKind::TypeMismatch, "Cannot parse operands as integers!".to_string(), Some(vec![a1, a2]));
                 Err(err.err().unwrap())
             }
         }
     }
 
+    #[allow(dead_code)]
     fn check_usize(&self, operand: Field) -> Result<usize, Error> {
         let item = operand.to_u();
         match item {
             Some(u) => Ok(u),
             None => {
-                let err = self.error("Cannot parse as usize!".to_string(), Some(vec![operand]));
+                let err = self.error(ErrorKind::TypeMismatch, "Cannot parse as usize!".to_string(), Some(vec![operand]));
+                Err(err.err().unwrap())
+            }
+        }
+    }
+
+    fn check_index(&self, operand: Field) -> Result<usize, Error> {
+        if let Field::U(u) = operand {
+            return Ok(u);
+        }
+        let index = self.check_int(operand.clone())?;
+        match usize::try_from(index) {
+            Ok(index) => Ok(index),
+            Err(_) => {
+                let err = self.error(ErrorKind::OutOfRange, "Index cannot be negative!".to_string(), Some(vec![operand]));
                 Err(err.err().unwrap())
             }
         }
     }
 
+    fn check_bit_index(&self, operand: Field) -> Result<u32, Error> {
+        let index = self.check_int(operand.clone())?;
+        if !(0..64).contains(&index) {
+            let err = self.error(ErrorKind::OutOfRange, "Bit index must be between 0 and 63!".to_string(), Some(vec![operand]));
+            return Err(err.err().unwrap());
+        }
+        Ok(index as u32)
+    }
+
     fn check_str(&self, operand: Field) -> Result<String, Error> {
         let item = operand.to_s();
         match item {
             Some(s) => Ok(s),
             None => {
-                let err = self.error("Cannot parse as string!".to_string(), Some(vec![operand]));
+                let err = self.error(ErrorKind::TypeMismatch, "Cannot parse as string!".to_string(), Some(vec![operand]));
                 Err(err.err().unwrap())
             }
         }
     }
 
-    fn get_input(&self) -> String{
+    fn get_input(&mut self) -> String{
         let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
+        match self.stdin.read_line(&mut input) {
             Ok(_goes_into_input_above) => {},
             Err(_no_updates_is_fine) => {},
         }
@@ -479,9 +1732,24 @@ impl Vm {
     }
 }
 
+fn describe(field: &Field) -> String {
+    match field {
+        Field::S(s) => format!("{:?} ({})", s.as_ref(), field.type_name()),
+        _ => format!("{} ({})", field, field.type_name())
+    }
+}
+
+impl Drop for Vm {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     #[test]
     fn test_push() -> Result<(),Error> {
@@ -490,7 +1758,30 @@ mod test {
         ], None)?;
 
         assert_eq!(vm.stack.len(), 1);
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4 as i64);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4_i64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmp() -> Result<(),Error> {
+        let mut vm = Vm::new(true);
+        let mut program = Program::new();
+        program.data.insert("@limit".to_string(), Field::from(10));
+        program.instructions = vec![
+            ins(OpCode::Push, 3),
+            ins(OpCode::Push, 5),
+            ins_e(OpCode::Cmp),
+            ins(OpCode::Push, 12),
+            ins(OpCode::Cmp, "@limit"),
+            Instruction::new(OpCode::Cmp, vec![Field::from(5), Field::from("@limit")]),
+            Instruction::new(OpCode::Cmp, vec![Field::from("@limit"), Field::from(10)]),
+        ];
+        vm.execute(program)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -1);
         Ok(())
     }
 
@@ -542,96 +1833,1005 @@ mod test {
     }
 
     #[test]
-    fn test_div() -> Result<(),Error>  {
+    fn test_add_wraps() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
-            ins(OpCode::Push, 12),
-            ins(OpCode::Push, 3),
-            ins_e(OpCode::Div)
+            ins(OpCode::Push, i64::MAX),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Add)
         ], None)?;
 
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MIN);
         Ok(())
     }
 
     #[test]
-    fn test_mod() -> Result<(),Error>  {
-        let mut vm = create_vm(vec![
-            ins(OpCode::Push, 13),
-            ins(OpCode::Push, 3),
-            ins_e(OpCode::Mod)
-        ], None)?;
-
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+    fn test_trap_on_overflow() -> Result<(),Error>  {
+        let run = |instructions: Vec<Instruction>| {
+            let mut vm = Vm::with_options(VmOptions { trap_on_overflow: true, ..Default::default() });
+            vm.execute(Program { instructions, ..Default::default() }).map(|_| vm)
+        };
+
+        let mut vm = run(vec![ins(OpCode::Push, 1), ins(OpCode::Push, 1), ins_e(OpCode::Add)])?;
+        assert_eq!(vm.pop_stack()?, Field::from(2));
+
+        let result = run(vec![ins(OpCode::Push, i64::MAX), ins(OpCode::Push, 1), ins_e(OpCode::Add)]);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Overflow);
+        let result = run(vec![ins(OpCode::Push, i64::MIN), ins(OpCode::Push, 1), ins_e(OpCode::Sub)]);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Overflow);
+        let result = run(vec![ins(OpCode::Push, i64::MAX), ins_e(OpCode::Inc)]);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Overflow);
+        let result = run(vec![ins(OpCode::Push, i64::MIN), ins_e(OpCode::Neg)]);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Overflow);
         Ok(())
     }
 
     #[test]
-    fn test_print() -> Result<(),Error>  {
-        let vm = create_vm(vec![
-            ins(OpCode::Push, 3),
-            ins_e(OpCode::Print)
+    fn test_adds() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, i64::MAX),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Adds)
         ], None)?;
 
-        assert_eq!(vm.stack.len(), 0);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MAX);
         Ok(())
     }
 
     #[test]
-    fn test_call() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@func".to_string(), 1);
+    fn test_subs() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
-            ins(OpCode::Call, "@func"),
-            ins(OpCode::Push, "should be on stack"),
-        ], Some(hashmap))?;
+            ins(OpCode::Push, i64::MIN),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Subs)
+        ], None)?;
 
-        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "should be on stack");
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MIN);
         Ok(())
     }
 
     #[test]
-    fn test_ret() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@func".to_string(), 2);
-        hashmap.insert("@end".to_string(), 5);
+    fn test_muls() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
-            ins(OpCode::Call, "@func"),
-            ins(OpCode::Jmp, "@end"),
-            ins(OpCode::Push, "test"),
-            ins_e(OpCode::Pop),
-            ins_e(OpCode::Ret),
-            ins(OpCode::Push, "should be on stack"),
-        ], Some(hashmap))?;
+            ins(OpCode::Push, i64::MIN),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Muls),
+            ins(OpCode::Push, i64::MAX),
+            ins(OpCode::Push, -2),
+            ins_e(OpCode::Muls),
+            ins(OpCode::Push, 6),
+            ins(OpCode::Push, 7),
+            ins_e(OpCode::Muls)
+        ], None)?;
 
-        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "should be on stack");
-        assert_eq!(vm.stack.len(), 0);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 42);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MIN);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MIN);
         Ok(())
     }
 
     #[test]
-    fn test_label() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@end".to_string(), 2);
-        let vm = create_vm(vec![
-            ins(OpCode::Jmp, "@end"),
+    fn test_add_mul_strings() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "abc"),
             ins(OpCode::Push, 1),
-        ], Some(hashmap))?;
+            ins_e(OpCode::Add),
+            ins(OpCode::Push, "ab"),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Mul),
+        ], None)?;
 
-        assert_eq!(vm.stack.len(), 0);
+        assert_eq!(vm.pop_stack()?, Field::from("ababab"));
+        assert_eq!(vm.pop_stack()?, Field::from("abc1"));
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "ab"),
+            ins(OpCode::Push, -1),
+            ins_e(OpCode::Mul),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        for times in [Field::from(4611686018427387904_usize), Field::from(i64::MAX), Field::from(MAX_STRING_LEN / 2 + 1)] {
+            let result = create_vm(vec![
+                ins(OpCode::Push, "ab"),
+                Instruction::new(OpCode::Push, vec![times]),
+                ins_e(OpCode::Mul),
+            ], None);
+            assert_eq!(result.err().unwrap().kind, ErrorKind::Overflow);
+        }
         Ok(())
     }
 
     #[test]
-    fn test_jmp() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@end".to_string(), 2);
-        let vm = create_vm(vec![
-            ins(OpCode::Jmp, "@end"),
-            ins(OpCode::Push, 1),
-        ], Some(hashmap))?;
-
-        assert_eq!(vm.stack.len(), 0);
-        Ok(())
+    fn test_arithmetic_mismatched_types() {
+        for opcode in [OpCode::Sub, OpCode::Div, OpCode::Mod, OpCode::Adds, OpCode::Subs, OpCode::Muls] {
+            let result = create_vm(vec![
+                ins(OpCode::Push, "text"),
+                ins(OpCode::Push, 1),
+                ins_e(opcode)
+            ], None);
+            let err = result.err().unwrap();
+            assert_eq!(err.kind, ErrorKind::TypeMismatch);
+            assert!(err.stacktrace.iter().any(|line| line.contains("text") && line.contains('1')));
+        }
+    }
+
+    #[test]
+    fn test_pow_sqrt() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, -3),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Pow),
+            ins(OpCode::Push, 17),
+            ins_e(OpCode::Sqrt),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -27);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, -1),
+            ins_e(OpCode::Pow),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, -4),
+            ins_e(OpCode::Sqrt),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_queries() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "héllo world"),
+            ins_e(OpCode::Strlen),
+            ins(OpCode::Push, "héllo world"),
+            ins(OpCode::Push, 6),
+            ins(OpCode::Push, 5),
+            ins_e(OpCode::Substr),
+            ins(OpCode::Push, "héllo"),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::CharAt),
+            ins(OpCode::Push, "héllo world"),
+            ins(OpCode::Push, "world"),
+            ins_e(OpCode::IndexOf),
+            ins(OpCode::Push, "héllo"),
+            ins(OpCode::Push, "x"),
+            ins_e(OpCode::IndexOf),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 6);
+        assert_eq!(vm.pop_stack()?.to_string(), "é");
+        assert_eq!(vm.pop_stack()?.to_string(), "world");
+        assert_eq!(vm.pop_stack()?.to_u().unwrap(), 11);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "abc"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Substr),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "abc"),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::CharAt),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "abc"),
+            ins(OpCode::Push, -1),
+            ins_e(OpCode::CharAt),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 42),
+            ins_e(OpCode::Strlen),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::TypeMismatch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_transforms() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "  Hello World \n"),
+            ins_e(OpCode::Trim),
+            ins_e(OpCode::Dup),
+            ins_e(OpCode::Upper),
+            ins_e(OpCode::Swap),
+            ins_e(OpCode::Lower),
+            ins(OpCode::Push, "o"),
+            ins(OpCode::Push, "0"),
+            ins_e(OpCode::Replace),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_string(), "hell0 w0rld");
+        assert_eq!(vm.pop_stack()?.to_string(), "HELLO WORLD");
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "abc"),
+            ins(OpCode::Push, ""),
+            ins(OpCode::Push, "x"),
+            ins_e(OpCode::Replace),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_join() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "a,b,,c"),
+            ins(OpCode::Push, ","),
+            ins_e(OpCode::Split),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_u().unwrap(), 4);
+        assert_eq!(vm.pop_stack()?.to_string(), "c");
+        assert_eq!(vm.pop_stack()?.to_string(), "");
+
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "a,b,,c"),
+            ins(OpCode::Push, ","),
+            ins_e(OpCode::Split),
+            ins(OpCode::Push, "-"),
+            ins_e(OpCode::Join),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_string(), "a-b--c");
+        assert!(vm.stack.is_empty());
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "a"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, "-"),
+            ins_e(OpCode::Join),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::StackUnderflow);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "kept"),
+            ins(OpCode::Push, "x"),
+            ins(OpCode::Push, 42),
+            ins(OpCode::Push, "{} = {} {{literal}}"),
+            ins_e(OpCode::Format),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_string(), "x = 42 {literal}");
+        assert_eq!(vm.pop_stack()?.to_string(), "kept");
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, "{} and {}"),
+            ins_e(OpCode::Format),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::StackUnderflow);
+        Ok(())
+    }
+
+    #[test]
+    fn test_assert() -> Result<(),Error>  {
+        create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Assert),
+        ], None)?;
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "42"),
+            ins(OpCode::Push, 42),
+            ins_e(OpCode::Assert),
+        ], None);
+        let err = result.err().unwrap();
+        assert_eq!(err.kind, ErrorKind::Assertion);
+        assert_eq!(err.message, "Assertion failed at 2:\n  left:  \"42\" (str)\n  right: 42 (int)");
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "a\nb"),
+            ins(OpCode::Push, "a\nc"),
+            ins_e(OpCode::Assert),
+        ], None);
+        assert!(result.err().unwrap().message.ends_with("--- left\n+++ right\n a\n-b\n+c"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_div() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 12),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Div)
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mod() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 13),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Mod)
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_div_mod_by_zero() -> Result<(),Error>  {
+        for opcode in [OpCode::Div, OpCode::Mod] {
+            let result = create_vm(vec![
+                ins(OpCode::Push, 13),
+                ins(OpCode::Push, 0),
+                ins_e(opcode)
+            ], None);
+            assert_eq!(result.err().unwrap().kind, ErrorKind::DivideByZero);
+        }
+
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, i64::MIN),
+            ins(OpCode::Push, -1),
+            ins_e(OpCode::Div)
+        ], None)?;
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), i64::MIN);
+        Ok(())
+    }
+
+    #[test]
+    fn test_print() -> Result<(),Error>  {
+        let vm = create_vm(vec![
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Print)
+        ], None)?;
+
+        assert_eq!(vm.stack.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_call() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 1);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins(OpCode::Push, "should be on stack"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "should be on stack");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ret() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 2);
+        hashmap.insert("@end".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins(OpCode::Jmp, "@end"),
+            ins(OpCode::Push, "test"),
+            ins_e(OpCode::Pop),
+            ins_e(OpCode::Ret),
+            ins(OpCode::Push, "should be on stack"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "should be on stack");
+        assert_eq!(vm.stack.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_leave() -> Result<(),Error>  {
+        let mut labels = HashMap::new();
+        labels.insert("@func".to_string(), 3);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "arg"),
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, 2),
+            ins(OpCode::Push, "scratch"),
+            ins_e(OpCode::Leave),
+            ins_e(OpCode::Ret),
+        ], Some(labels))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?, Field::from("arg"));
+
+        let result = create_vm(vec![
+            ins(OpCode::Enter, 1),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let mut labels = HashMap::new();
+        labels.insert("@func".to_string(), 2);
+        let result = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, i64::MAX),
+        ], Some(labels));
+        let err = result.err().unwrap();
+        assert_eq!(err.kind, ErrorKind::OutOfRange);
+        assert_eq!(err.message, "Cannot enter a frame with more than 65536 locals!");
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry() -> Result<(),Error>  {
+        let mut labels = HashMap::new();
+        labels.insert("@main".to_string(), 1);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "skipped"),
+            ins(OpCode::Push, "entry"),
+        ], Some(labels))?;
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?, Field::from("entry"));
+
+        let mut vm = Vm::new(false);
+        let result = vm.execute(Program { entry: Some("@missing".to_string()), ..Default::default() });
+        assert_eq!(result.err().unwrap().kind, ErrorKind::UnknownLabel);
+        Ok(())
+    }
+
+    #[test]
+    fn test_locals() -> Result<(),Error>  {
+        let mut labels = HashMap::new();
+        labels.insert("@outer".to_string(), 2);
+        labels.insert("@inner".to_string(), 8);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Call, "@outer"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, 1),
+            ins(OpCode::Push, "outer"),
+            ins(OpCode::LocalSet, 0),
+            ins(OpCode::Call, "@inner"),
+            ins(OpCode::LocalGet, 0),
+            ins_e(OpCode::Ret),
+            ins(OpCode::Enter, 1),
+            ins(OpCode::Push, "inner"),
+            ins(OpCode::LocalSet, 0),
+            ins_e(OpCode::Leave),
+            ins_e(OpCode::Ret),
+        ], Some(labels))?;
+
+        assert_eq!(vm.stack.len(), 2);
+        assert_eq!(vm.pop_stack()?, Field::from("outer"));
+
+        let mut labels = HashMap::new();
+        labels.insert("@func".to_string(), 2);
+        let result = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, 1),
+            ins(OpCode::LocalGet, 1),
+        ], Some(labels));
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let mut labels = HashMap::new();
+        labels.insert("@func".to_string(), 2);
+        let result = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, 1),
+            ins_e(OpCode::Pop),
+            ins(OpCode::LocalGet, 0),
+        ], Some(labels));
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_fini() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        vm.execute(Program {
+            instructions: vec![
+                ins(OpCode::Push, "main"),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Push, "init"),
+                ins_e(OpCode::Ret),
+                ins(OpCode::Push, "fini"),
+                ins_e(OpCode::Ret),
+            ],
+            init: vec![2],
+            fini: vec![4],
+            ..Default::default()
+        })?;
+
+        assert_eq!(vm.pop_stack()?, Field::from("fini"));
+        assert_eq!(vm.pop_stack()?, Field::from("main"));
+        assert_eq!(vm.pop_stack()?, Field::from("init"));
+        assert!(vm.call_stack.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hlt_in_init() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        let status = vm.execute(Program {
+            instructions: vec![
+                ins(OpCode::Push, "main"),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Call, "@setup"),
+                ins_e(OpCode::Ret),
+                ins(OpCode::Push, "fini"),
+                ins_e(OpCode::Ret),
+                ins(OpCode::Push, "init"),
+                ins(OpCode::Hlt, 3),
+                ins(OpCode::Push, "unreachable"),
+                ins_e(OpCode::Ret),
+            ],
+            labels: HashMap::from([("@setup".to_string(), 6)]),
+            init: vec![2, 8],
+            fini: vec![4],
+            ..Default::default()
+        })?;
+
+        assert_eq!(status.code, 3);
+        assert_eq!(vm.pop_stack()?, Field::from("fini"));
+        assert_eq!(vm.pop_stack()?, Field::from("init"));
+        assert!(vm.stack.is_empty());
+        assert!(vm.call_stack.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_from_and_range() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        vm.load(Program {
+            instructions: vec![
+                ins(OpCode::Push, 1),
+                ins(OpCode::Push, 2),
+                ins_e(OpCode::Add),
+                ins(OpCode::Push, "handled"),
+                ins_e(OpCode::Ret),
+            ],
+            labels: HashMap::from([("@handler".to_string(), 3)]),
+            ..Default::default()
+        })?;
+
+        vm.execute_range(0, 3)?;
+        assert_eq!(vm.stack.len(), 1);
+        vm.execute_from("@handler")?;
+        vm.execute_from("@handler")?;
+        assert_eq!(vm.pop_stack()?, Field::from("handled"));
+        assert_eq!(vm.pop_stack()?, Field::from("handled"));
+        assert_eq!(vm.pop_stack()?, Field::from(3));
+        assert!(vm.call_stack.is_empty());
+
+        assert_eq!(vm.execute_range(2, 9).err().unwrap().kind, ErrorKind::OutOfRange);
+        assert_eq!(vm.execute_from("@missing").err().unwrap().kind, ErrorKind::UnknownLabel);
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_range_leaving_range() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        vm.load(Program {
+            instructions: vec![
+                ins(OpCode::Call, "@handler"),
+                ins(OpCode::Push, "after"),
+                ins(OpCode::Push, "handled"),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Push, "outside"),
+                ins_e(OpCode::Ret),
+            ],
+            labels: HashMap::from([("@handler".to_string(), 2), ("@outside".to_string(), 4)]),
+            ..Default::default()
+        })?;
+
+        assert_eq!(vm.execute_range(0, 2).err().unwrap().kind, ErrorKind::OutOfRange);
+        assert!(vm.call_stack.is_empty());
+
+        vm.execute_range(2, 6)?;
+        assert_eq!(vm.pop_stack()?, Field::from("handled"));
+        assert!(vm.call_stack.is_empty());
+
+        vm.execute_from("@handler")?;
+        assert_eq!(vm.pop_stack()?, Field::from("handled"));
+        assert!(vm.call_stack.is_empty());
+
+        vm.execute_from("@outside")?;
+        assert_eq!(vm.pop_stack()?, Field::from("outside"));
+        assert!(vm.stack.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trap_fallthrough() {
+        let program = Program {
+            instructions: vec![
+                Instruction::new(OpCode::Call, vec![Field::from("@func")]),
+                Instruction::new(OpCode::Push, vec![Field::from(1)]),
+                Instruction::new(OpCode::Ret, vec![]),
+            ],
+            labels: HashMap::from([("@func".to_string(), 2)]),
+            ..Default::default()
+        };
+
+        let mut vm = Vm::new(false);
+        assert_ne!(vm.execute(program.clone()).err().unwrap().kind, ErrorKind::Fallthrough);
+
+        let mut vm = Vm::with_options(VmOptions { trap_fallthrough: true, ..Default::default() });
+        let result = vm.execute(program);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Fallthrough);
+    }
+
+    #[test]
+    fn test_call_computed() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@handler".to_string(), 3);
+        let mut vm = Vm::new(false);
+        vm.execute(Program {
+            instructions: vec![
+                ins(OpCode::Push, "@callback"),
+                ins_e(OpCode::Call),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Push, "handled"),
+                ins_e(OpCode::Ret),
+            ],
+            labels: hashmap,
+            data: HashMap::from([("@callback".to_string(), Field::from("@handler"))]),
+            ..Default::default()
+        })?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?, Field::from("handled"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tailcall() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@countdown".to_string(), 3);
+        hashmap.insert("@done".to_string(), 10);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 1000),
+            ins(OpCode::Call, "@countdown"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Enter, 1),
+            ins_e(OpCode::Leave),
+            ins_e(OpCode::Dec),
+            ins_e(OpCode::Dup),
+            ins(OpCode::Push, 0),
+            ins(OpCode::Je, "@done"),
+            ins(OpCode::TailCall, "@countdown"),
+            ins(OpCode::Load, "$__callstack_size"),
+            ins_e(OpCode::Ret),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.pop_stack()?, Field::from(1_usize));
+        assert_eq!(vm.pop_stack()?, Field::from(0));
+        assert!(vm.call_stack.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ret_args() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 4);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 3),
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Arg, 1),
+            ins(OpCode::Arg, 0),
+            ins_e(OpCode::Add),
+            ins(OpCode::Ret, 2),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?, Field::from(5));
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 2);
+        let result = create_vm(vec![
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Ret, 1),
+        ], Some(hashmap));
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arg() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 4);
+        hashmap.insert("@end".to_string(), 8);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins(OpCode::Push, 3),
+            ins(OpCode::Call, "@func"),
+            ins(OpCode::Jmp, "@end"),
+            ins(OpCode::Push, "local"),
+            ins(OpCode::Arg, 1),
+            ins(OpCode::Arg, 0),
+            ins_e(OpCode::Ret),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 3);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "local");
+        assert_eq!(vm.stack.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arg_out_of_range() {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@func".to_string(), 2);
+        let result = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins(OpCode::Call, "@func"),
+            ins(OpCode::Arg, 1),
+        ], Some(hashmap));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_error_includes_call_chain() {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@outer".to_string(), 2);
+        hashmap.insert("@inner".to_string(), 4);
+        let result = create_vm(vec![
+            ins(OpCode::Call, "@outer"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Call, "@inner"),
+            ins_e(OpCode::Pop),
+            ins_e(OpCode::Pop),
+        ], Some(hashmap));
+
+        let stacktrace = result.err().unwrap().stacktrace;
+        let frames: Vec<&String> = stacktrace.iter().filter(|s| s.contains("<-- called")).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], "3\t | call @inner <-- called @inner from here, frame base 1");
+        assert_eq!(frames[1], "0\t | call @outer <-- called @outer from here, frame base 0");
+    }
+
+    #[test]
+    fn test_error_in_section_has_no_call_site() {
+        let mut vm = Vm::new(false);
+        let result = vm.execute(Program {
+            instructions: vec![
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Call, "@setup"),
+                ins_e(OpCode::Ret),
+                ins_e(OpCode::Pop),
+            ],
+            labels: HashMap::from([("@setup".to_string(), 3)]),
+            init: vec![1],
+            ..Default::default()
+        });
+
+        let stacktrace = result.err().unwrap().stacktrace;
+        let frames: Vec<&String> = stacktrace.iter().skip_while(|s| !s.contains("<-- error")).skip(1).collect();
+        assert_eq!(frames, vec!["1\t | call @setup <-- called @setup from here, frame base 0", "\t | entered #init, frame base 0"]);
+    }
+
+    #[test]
+    fn test_label() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@end".to_string(), 2);
+        let vm = create_vm(vec![
+            ins(OpCode::Jmp, "@end"),
+            ins(OpCode::Push, 1),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_jmp() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@end".to_string(), 2);
+        let vm = create_vm(vec![
+            ins(OpCode::Jmp, "@end"),
+            ins(OpCode::Push, 1),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_jnull_jnotnull() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@allocate".to_string(), 2);
+        hashmap.insert("@store".to_string(), 5);
+        hashmap.insert("@done".to_string(), 9);
+        let vm = create_vm(vec![
+            Instruction::new(OpCode::Jnull, vec![Field::from("$var"), Field::from("@allocate")]),
+            ins(OpCode::Push, "missing var is not null"),
+            ins(OpCode::Alloc, "$var"),
+            Instruction::new(OpCode::Jnull, vec![Field::from("$var"), Field::from("@store")]),
+            ins(OpCode::Push, "empty var is not null"),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Store, "$var"),
+            Instruction::new(OpCode::Jnotnull, vec![Field::from("$var"), Field::from("@done")]),
+            ins(OpCode::Push, "stored var is null"),
+            ins_e(OpCode::Nop),
+        ], Some(hashmap))?;
+
+        assert!(vm.stack.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jz_jnz() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@zero".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 3),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Sub),
+            ins(OpCode::Jz, "@zero"),
+            ins(OpCode::Push, "not zero"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0);
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@loop".to_string(), 1);
+        hashmap.insert("@end".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 3),
+            ins(OpCode::Push, "tick"),
+            ins_e(OpCode::Swap),
+            ins_e(OpCode::Dec),
+            ins(OpCode::Jnz, "@loop"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 4);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_loop() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@body".to_string(), 2);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 0),
+            ins(OpCode::Push, 4),
+            ins_e(OpCode::Swap),
+            ins_e(OpCode::Inc),
+            ins_e(OpCode::Swap),
+            ins(OpCode::Loop, "@body"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_loop_ends_on_non_positive_counter() -> Result<(),Error>  {
+        for counter in [0, -3] {
+            let mut hashmap = HashMap::new();
+            hashmap.insert("@body".to_string(), 2);
+            let mut vm = create_vm(vec![
+                ins(OpCode::Push, 0),
+                ins(OpCode::Push, counter),
+                ins_e(OpCode::Swap),
+                ins_e(OpCode::Inc),
+                ins_e(OpCode::Swap),
+                ins(OpCode::Loop, "@body"),
+            ], Some(hashmap))?;
+
+            assert_eq!(vm.stack.len(), 1);
+            assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_jmp_computed() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@target".to_string(), 4);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "@target"),
+            ins_e(OpCode::Jmp),
+            ins(OpCode::Push, "skipped"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Push, 7),
+            ins_e(OpCode::Jmp),
+            ins(OpCode::Push, "skipped"),
+            ins(OpCode::Push, "landed"),
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?, Field::from("landed"));
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "@missing"),
+            ins_e(OpCode::Jmp),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::UnknownLabel);
+        Ok(())
+    }
+
+    #[test]
+    fn test_switch() -> Result<(),Error>  {
+        for (n, expected) in [(0, "zero"), (1, "one"), (2, "default"), (-1, "default")] {
+            let mut hashmap = HashMap::new();
+            hashmap.insert("@zero".to_string(), 2);
+            hashmap.insert("@one".to_string(), 4);
+            hashmap.insert("@default".to_string(), 6);
+            let mut vm = create_vm(vec![
+                ins(OpCode::Push, n),
+                Instruction::new(OpCode::Switch, vec![Field::from("@zero"), Field::from("@one"), Field::from("@default")]),
+                ins(OpCode::Push, "zero"),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Push, "one"),
+                ins_e(OpCode::Hlt),
+                ins(OpCode::Push, "default"),
+            ], Some(hashmap))?;
+
+            assert_eq!(vm.stack.len(), 1);
+            assert_eq!(vm.pop_stack()?, Field::from(expected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_caches() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        vm.execute(Program {
+            instructions: vec![
+                ins(OpCode::Push, 3),
+                ins(OpCode::Push, "@greeting"),
+                ins_e(OpCode::Pop),
+                ins(OpCode::Loop, "@loop"),
+            ],
+            labels: HashMap::from([("@loop".to_string(), 1)]),
+            data: HashMap::from([("@greeting".to_string(), Field::from("hi"))]),
+            ..Default::default()
+        })?;
+
+        assert_eq!(vm.label_cache, vec![None, None, None, Some(1)]);
+        assert_eq!(vm.data_cache[1], Some(Field::from("hi")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_jmp_numeric() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Jmp, 2),
+            ins(OpCode::Push, "skipped"),
+            ins(OpCode::Push, "landed"),
+        ], None)?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "landed");
+
+        let result = create_vm(vec![
+            ins(OpCode::Jmp, 5),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Jmp, -1),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+        Ok(())
     }
 
     #[test]
@@ -765,91 +2965,357 @@ mod test {
     }
 
     #[test]
-    fn test_jl() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@less".to_string(), 4);
-        let vm = create_vm(vec![
-            ins(OpCode::Push, 4),
-            ins(OpCode::Push, 7),
-            ins(OpCode::Jl, "@less"),
-            ins(OpCode::Push, 5)
-        ], Some(hashmap))?;
+    fn test_jl() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@less".to_string(), 4);
+        let vm = create_vm(vec![
+            ins(OpCode::Push, 4),
+            ins(OpCode::Push, 7),
+            ins(OpCode::Jl, "@less"),
+            ins(OpCode::Push, 5)
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 0);
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@less".to_string(), 4);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 7),
+            ins(OpCode::Push, 4),
+            ins(OpCode::Jl, "@less"),
+            ins(OpCode::Push, 5)
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_jg() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@greater".to_string(), 4);
+        let vm = create_vm(vec![
+            ins(OpCode::Push, 7),
+            ins(OpCode::Push, 4),
+            ins(OpCode::Jg, "@greater"),
+            ins(OpCode::Push, 5)
+        ], Some(hashmap))?;
+
+        assert_eq!(vm.stack.len(), 0);
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@greater".to_string(), 4);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 4),
+            ins(OpCode::Push, 7),
+            ins(OpCode::Jg, "@greater"),
+            ins(OpCode::Push, 5)
+        ], Some(hashmap))?;
+
+
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_and_unsigned_jumps() -> Result<(),Error>  {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@less".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, -1),
+            ins(OpCode::Push, 1_usize),
+            ins(OpCode::Jl, "@less"),
+            ins(OpCode::Push, "not less"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Push, "less"),
+        ], Some(hashmap))?;
+        assert_eq!(vm.pop_stack()?, Field::from("less"));
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@above".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, -1),
+            ins(OpCode::Push, 1_usize),
+            ins(OpCode::Ja, "@above"),
+            ins(OpCode::Push, "not above"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Push, "above"),
+        ], Some(hashmap))?;
+        assert_eq!(vm.pop_stack()?, Field::from("above"));
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("@below".to_string(), 5);
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, -1),
+            ins(OpCode::Jb, "@below"),
+            ins(OpCode::Push, "not below"),
+            ins_e(OpCode::Hlt),
+            ins(OpCode::Push, "below"),
+        ], Some(hashmap))?;
+        assert_eq!(vm.pop_stack()?, Field::from("below"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dup() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins_e(OpCode::Dup),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inc() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins_e(OpCode::Inc),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 11);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dec() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins_e(OpCode::Dec),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_abs() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, -10),
+            ins_e(OpCode::Abs),
+            ins(OpCode::Push, 7),
+            ins_e(OpCode::Abs),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 7);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_neg() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 10),
+            ins_e(OpCode::Neg),
+            ins(OpCode::Push, -3),
+            ins_e(OpCode::Neg),
+            ins(OpCode::Push, 1_usize),
+            ins_e(OpCode::Neg),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_u().unwrap(), usize::MAX);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 3);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, -10),
+            ins_e(OpCode::Sign),
+            ins(OpCode::Push, 0),
+            ins_e(OpCode::Sign),
+            ins(OpCode::Push, 42),
+            ins_e(OpCode::Sign),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_counts() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 0b1011_0000),
+            ins_e(OpCode::Popcnt),
+            ins(OpCode::Push, 0b1011_0000),
+            ins_e(OpCode::Ctz),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Clz),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 63);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 4);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_test_set_clear_toggle() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, 0b0100),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Bt),
+            ins(OpCode::Push, 0b0100),
+            ins(OpCode::Push, 0),
+            ins_e(OpCode::Bts),
+            ins(OpCode::Push, 0b0101),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Btr),
+            ins(OpCode::Push, 0b0101),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Btc),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0b0111);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0b0001);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0b0101);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_index_out_of_range() {
+        let result = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, 64),
+            ins_e(OpCode::Bts),
+        ], None);
 
-        assert_eq!(vm.stack.len(), 0);
+        assert!(result.is_err());
+    }
 
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@less".to_string(), 4);
+    #[test]
+    fn test_and_or_not() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
-            ins(OpCode::Push, 7),
-            ins(OpCode::Push, 4),
-            ins(OpCode::Jl, "@less"),
-            ins(OpCode::Push, 5)
-        ], Some(hashmap))?;
+            ins(OpCode::Push, 0b1100),
+            ins(OpCode::Push, 0b1010),
+            ins_e(OpCode::And),
+            ins(OpCode::Push, 0b1100),
+            ins(OpCode::Push, 0b1010),
+            ins_e(OpCode::Or),
+            ins(OpCode::Push, 0),
+            ins_e(OpCode::Not),
+        ], None)?;
 
-        assert_eq!(vm.stack.len(), 1);
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 5);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -1);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0b1110);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 0b1000);
         Ok(())
     }
 
     #[test]
-    fn test_jg() -> Result<(),Error>  {
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@greater".to_string(), 4);
-        let vm = create_vm(vec![
-            ins(OpCode::Push, 7),
-            ins(OpCode::Push, 4),
-            ins(OpCode::Jg, "@greater"),
-            ins(OpCode::Push, 5)
-        ], Some(hashmap))?;
+    fn test_and_mismatched_types() {
+        let result = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, 1_usize),
+            ins_e(OpCode::And),
+        ], None);
 
-        assert_eq!(vm.stack.len(), 0);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::TypeMismatch);
+    }
 
-        let mut hashmap = HashMap::new();
-        hashmap.insert("@greater".to_string(), 4);
+    #[test]
+    fn test_shifts() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
+            ins(OpCode::Push, 3),
             ins(OpCode::Push, 4),
-            ins(OpCode::Push, 7),
-            ins(OpCode::Jg, "@greater"),
-            ins(OpCode::Push, 5)
-        ], Some(hashmap))?;
-
+            ins_e(OpCode::Shl),
+            ins(OpCode::Push, -16),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Sar),
+            ins(OpCode::Push, -16),
+            ins(OpCode::Push, 60),
+            ins_e(OpCode::Shr),
+            ins(OpCode::Push, 48_usize),
+            ins(OpCode::Push, 4),
+            ins_e(OpCode::Sar),
+        ], None)?;
 
-        assert_eq!(vm.stack.len(), 1);
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 5);
+        assert_eq!(vm.pop_stack()?.to_u().unwrap(), 3);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 15);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), -4);
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 48);
         Ok(())
     }
 
     #[test]
-    fn test_dup() -> Result<(),Error>  {
+    fn test_cast() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
-            ins(OpCode::Push, 10),
-            ins_e(OpCode::Dup),
+            ins(OpCode::Push, "42"),
+            ins(OpCode::Cast, "int"),
+            ins(OpCode::Push, 7),
+            ins(OpCode::Cast, "uint"),
+            ins(OpCode::Push, 3),
+            ins(OpCode::Cast, "str"),
         ], None)?;
 
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 10);
+        assert_eq!(vm.pop_stack()?, Field::from("3"));
+        assert_eq!(vm.pop_stack()?, Field::from(7_usize));
+        assert_eq!(vm.pop_stack()?, Field::from(42));
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, -1),
+            ins(OpCode::Cast, "uint"),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::TypeMismatch);
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, "abc"),
+            ins(OpCode::Cast, "int"),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::TypeMismatch);
         Ok(())
     }
 
     #[test]
-    fn test_inc() -> Result<(),Error>  {
-        let mut vm = create_vm(vec![
-            ins(OpCode::Push, 10),
-            ins_e(OpCode::Inc),
-        ], None)?;
+    fn test_rand_seed() -> Result<(),Error>  {
+        let rolls = |seed: i64| -> Result<Vec<Field>, Error> {
+            let mut instructions = vec![ins(OpCode::Push, seed), ins_e(OpCode::Seed)];
+            for _ in 0..20 {
+                instructions.push(ins(OpCode::Push, 1));
+                instructions.push(ins(OpCode::Push, 6));
+                instructions.push(ins_e(OpCode::Rand));
+            }
+            Ok(create_vm(instructions, None)?.stack.to_vec().clone())
+        };
 
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 11);
+        let first = rolls(7)?;
+        assert_eq!(first, rolls(7)?);
+        assert_ne!(first, rolls(8)?);
+        assert!(first.iter().all(|f| (1..=6).contains(&f.to_i().unwrap())));
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 6),
+            ins(OpCode::Push, 1),
+            ins_e(OpCode::Rand),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
         Ok(())
     }
 
     #[test]
-    fn test_dec() -> Result<(),Error>  {
-        let mut vm = create_vm(vec![
-            ins(OpCode::Push, 10),
-            ins_e(OpCode::Dec),
-        ], None)?;
-
-        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 9);
+    fn test_deterministic() -> Result<(),Error>  {
+        let roll = |vm: &mut Vm| -> Result<Field, Error> {
+            execute(vm, vec![ins(OpCode::Push, 0), ins(OpCode::Push, 1000), ins_e(OpCode::Rand)], None)?;
+            vm.pop_stack()
+        };
+
+        let mut first = Vm::with_options(VmOptions::deterministic(3));
+        let mut second = Vm::with_options(VmOptions::deterministic(3));
+        let value = roll(&mut first)?;
+        assert_eq!(value, roll(&mut second)?);
+
+        first.reset();
+        assert_eq!(value, roll(&mut first)?);
         Ok(())
     }
 
@@ -882,7 +3348,7 @@ mod test {
     fn test_alloc_store() -> Result<(),Error>  {
         let mut vm = create_vm(vec![
             ins(OpCode::Alloc, "$myvar"),
-            ins(OpCode::Push, 20 as usize),
+            ins(OpCode::Push, 20_usize),
             ins(OpCode::Store, "$myvar"),
             ins(OpCode::Alloc, "$myvar1"),
             ins(OpCode::Push, "hey this is my lame string"),
@@ -900,16 +3366,88 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_load_store_stack_slots() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "bottom"),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Load, 2),
+            ins(OpCode::Push, "replaced"),
+            ins(OpCode::Store, 1),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "bottom");
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "replaced");
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "bottom");
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Load, 1),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let mut vm = create_vm(vec![
+            ins(OpCode::Push, "bottom"),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Load, 1_usize),
+            ins(OpCode::Push, "replaced"),
+            ins(OpCode::Store, 2_usize),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "bottom");
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "replaced");
+
+        let result = create_vm(vec![
+            ins(OpCode::Push, 1),
+            ins(OpCode::Load, 1_usize),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::OutOfRange);
+
+        let result = create_vm(vec![
+            ins(OpCode::Free, 1),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::TypeMismatch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_xchg() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Alloc, "$a"),
+            ins(OpCode::Alloc, "$b"),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Store, "$a"),
+            ins(OpCode::Push, "two"),
+            ins(OpCode::Store, "$b"),
+            Instruction::new(OpCode::Xchg, vec![Field::from("$a"), Field::from("$b")]),
+            ins(OpCode::Load, "$a"),
+            ins(OpCode::Load, "$b"),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_i().unwrap(), 1);
+        assert_eq!(vm.pop_stack()?.to_str().unwrap(), "two");
+
+        let result = create_vm(vec![
+            ins(OpCode::Alloc, "$a"),
+            Instruction::new(OpCode::Xchg, vec![Field::from("$a"), Field::from("$missing")]),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Heap);
+        Ok(())
+    }
+
     #[test]
     fn test_alloc_load() -> Result<(),Error>  {
         let mut hashmap = HashMap::new();
         hashmap.insert("@match".to_string(), 4);
         let mut vm = create_vm(vec![
             ins(OpCode::Alloc, "$myvar"),
-            ins(OpCode::Push, 20 as usize),
+            ins(OpCode::Push, 20_usize),
             ins(OpCode::Store, "$myvar"),
             ins(OpCode::Load, "$__stack_size"),
-            ins(OpCode::Push, 1),
+            ins(OpCode::Push, 0),
             ins(OpCode::Jl, "@end"),
             ins(OpCode::Push, 45),
             ins_e(OpCode::Nop)
@@ -921,6 +3459,480 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_error_window_and_resolved_operands() {
+        let mut vm = Vm::with_options(VmOptions { error_window: 1, ..Default::default() });
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$myvar"),
+            ins(OpCode::Push, "not a number"),
+            ins(OpCode::Store, "$myvar"),
+            ins(OpCode::Push, 1),
+            ins(OpCode::Load, "$myvar"),
+            ins_e(OpCode::Sub),
+            ins_e(OpCode::Nop),
+            ins_e(OpCode::Nop),
+        ], None);
+
+        let stacktrace = result.err().unwrap().stacktrace;
+        assert_eq!(stacktrace.len(), 3);
+        assert_eq!(stacktrace[0], "4\t | load $myvar");
+        assert!(stacktrace[1].starts_with("5\t | sub <-- error occurred here"));
+        assert_eq!(stacktrace[2], "6\t | nop");
+
+        let mut vm = Vm::with_options(VmOptions { error_window: 0, ..Default::default() });
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$myvar"),
+            ins(OpCode::Push, 0),
+            ins(OpCode::Store, "$myvar"),
+            ins(OpCode::Alloc, "$myvar"),
+        ], None);
+
+        let stacktrace = result.err().unwrap().stacktrace;
+        assert_eq!(stacktrace.len(), 1);
+        assert!(stacktrace[0].ends_with("(resolved: $myvar = 0)"));
+    }
+
+    #[test]
+    fn test_error_color() {
+        let mut vm = Vm::with_options(VmOptions { error_color: true, ..Default::default() });
+        let result = execute(&mut vm, vec![
+            ins_e(OpCode::Pop),
+        ], None);
+
+        let stacktrace = result.err().unwrap().stacktrace;
+        assert_eq!(stacktrace[0], "0\t | \x1b[1;31mpop <-- error occurred here\x1b[0m");
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let mut vm = Vm::with_options(VmOptions { reflection: false, heap_size: Some(1), ..Default::default() });
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$first"),
+            ins(OpCode::Alloc, "$second"),
+        ], None);
+
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Heap);
+    }
+
+    #[test]
+    fn test_heap_size_ignores_reflection() -> Result<(),Error>  {
+        let mut vm = Vm::with_options(VmOptions { heap_size: Some(1), ..Default::default() });
+        execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$first"),
+            ins(OpCode::Load, "$__pc"),
+            ins(OpCode::Store, "$first"),
+        ], None)?;
+        assert_eq!(vm.load_heap(&Field::from("$first"))?.to_u().unwrap(), 1);
+
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$second"),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Heap);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_input() -> Result<(),Error>  {
+        let vm = Vm::new(false);
+        assert_eq!(vm.parse_input(Field::from("-12"), "int")?.to_i().unwrap(), -12);
+        assert_eq!(vm.parse_input(Field::from("12"), "uint")?.to_u().unwrap(), 12);
+        assert_eq!(vm.parse_input(Field::from("yes"), "char")?.to_string(), "y");
+        assert_eq!(vm.parse_input(Field::from("twelve"), "int").err().unwrap().kind, ErrorKind::TypeMismatch);
+        assert_eq!(vm.parse_input(Field::from(""), "char").err().unwrap().kind, ErrorKind::TypeMismatch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_streams() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        vm.set_stdin(io::Cursor::new("20\nsecond line\n"));
+        vm.capture_output();
+        execute(&mut vm, vec![
+            ins(OpCode::Input, "int"),
+            ins_e(OpCode::Inc),
+            ins_e(OpCode::Println),
+            ins_e(OpCode::Input),
+            ins_e(OpCode::Print),
+        ], None)?;
+
+        assert_eq!(vm.take_output(), "21\nsecond line");
+        assert_eq!(vm.take_output(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_files() -> Result<(),Error>  {
+        let path = std::env::temp_dir().join(format!("opvm-test-files-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut vm = Vm::with_options(VmOptions { capabilities: vec![Capability::File], ..Default::default() });
+        execute(&mut vm, vec![
+            ins(OpCode::Push, path),
+            ins(OpCode::Push, "w"),
+            ins_e(OpCode::FileOpen),
+            ins_e(OpCode::Dup),
+            ins(OpCode::Push, "hello file"),
+            ins_e(OpCode::FileWrite),
+            ins_e(OpCode::FileClose),
+            ins(OpCode::Push, path),
+            ins(OpCode::Push, "r"),
+            ins_e(OpCode::FileOpen),
+            ins_e(OpCode::Dup),
+            ins_e(OpCode::FileRead),
+            ins_e(OpCode::Swap),
+            ins_e(OpCode::FileClose),
+        ], None)?;
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(vm.pop_stack()?.to_string(), "hello file");
+
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Push, 7),
+            ins_e(OpCode::FileClose),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Io);
+
+        let mut vm = Vm::new(false);
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Push, path),
+            ins(OpCode::Push, "r"),
+            ins_e(OpCode::FileOpen),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Capability);
+        Ok(())
+    }
+
+    #[test]
+    fn test_env() -> Result<(),Error>  {
+        let mut vm = Vm::with_options(VmOptions {
+            args: vec!["first".to_string(), "second".to_string()],
+            capabilities: vec![Capability::Env],
+            ..Default::default()
+        });
+        execute(&mut vm, vec![
+            ins(OpCode::Push, "PATH"),
+            ins_e(OpCode::Env),
+            ins(OpCode::Push, "OPVM_TEST_UNSET_VARIABLE"),
+            ins_e(OpCode::Env),
+            ins_e(OpCode::Cwd),
+            ins_e(OpCode::Args),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_u().unwrap(), 2);
+        assert_eq!(vm.pop_stack()?.to_string(), "second");
+        assert_eq!(vm.pop_stack()?.to_string(), "first");
+        assert_eq!(vm.pop_stack()?.to_string(), env::current_dir().unwrap().display().to_string());
+        assert_eq!(vm.pop_stack()?.to_string(), "");
+        assert_eq!(vm.pop_stack()?.to_string(), env::var("PATH").unwrap_or_default());
+
+        let mut vm = Vm::new(false);
+        let result = execute(&mut vm, vec![ins_e(OpCode::Cwd)], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Capability);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_symbols() -> Result<(),Error>  {
+        let program = Program {
+            instructions: vec![ins(OpCode::Push, "@missing"), ins(OpCode::Push, "@found"), ins_e(OpCode::Nop)],
+            labels: HashMap::from([("@found".to_string(), 2)]),
+            ..Default::default()
+        };
+        Vm::new(false).execute(program.clone())?;
+
+        let result = Vm::new(false).execute(Program { strict_symbols: vec![0], ..program.clone() });
+        assert_eq!(result.err().unwrap().kind, ErrorKind::UnknownLabel);
+
+        Vm::new(false).execute(Program { strict_symbols: vec![1], ..program })?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit_status() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        let status = vm.execute(Program { instructions: vec![
+            ins(OpCode::Push, "result"),
+            ins(OpCode::Hlt, 3),
+            ins_e(OpCode::Pop),
+        ], ..Default::default() })?;
+
+        assert_eq!(status.code, 3);
+        assert!(!status.success());
+        assert_eq!(status.result.unwrap().to_string(), "result");
+
+        let status = vm.execute(Program { instructions: vec![ins_e(OpCode::Nop)], ..Default::default() })?;
+        assert!(status.success());
+
+        vm.reset();
+        let status = vm.execute(Program { instructions: vec![ins_e(OpCode::Nop)], ..Default::default() })?;
+        assert_eq!(status, ExitStatus::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let mut vm = Vm::with_options(VmOptions { capabilities: vec![], ..Default::default() });
+        let result = execute(&mut vm, vec![
+            ins_e(OpCode::Input),
+        ], None);
+
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Capability);
+    }
+
+    #[test]
+    fn test_arena() -> Result<(),Error> {
+        let vm = create_vm(vec![
+            ins(OpCode::Alloc, "$outer"),
+            ins_e(OpCode::ArenaBegin),
+            ins(OpCode::Alloc, "$first"),
+            ins_e(OpCode::ArenaBegin),
+            ins(OpCode::Alloc, "$nested"),
+            ins_e(OpCode::ArenaEnd),
+            ins(OpCode::Alloc, "$second"),
+            ins(OpCode::Free, "$second"),
+        ], None)?;
+
+        assert!(vm.heap.contains_key("$first"));
+        assert!(!vm.heap.contains_key("$nested"));
+
+        let vm = create_vm(vec![
+            ins(OpCode::Alloc, "$outer"),
+            ins_e(OpCode::ArenaBegin),
+            ins(OpCode::Alloc, "$first"),
+            ins(OpCode::Alloc, "$second"),
+            ins(OpCode::Free, "$second"),
+            ins_e(OpCode::ArenaEnd),
+        ], None)?;
+
+        assert!(vm.heap.contains_key("$outer"));
+        assert!(!vm.heap.contains_key("$first"));
+        assert_eq!(vm.heap.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<(),Error>  {
+        let mut vm = Vm::new(false);
+        execute(&mut vm, vec![
+            ins(OpCode::Alloc, "$a"),
+            ins(OpCode::Alloc, "$b"),
+            ins(OpCode::Push, "a long string value"),
+            ins(OpCode::Store, "$a"),
+            ins(OpCode::Free, "$a"),
+            ins(OpCode::Free, "$b"),
+        ], None)?;
+
+        let stats = vm.stats();
+        assert_eq!(stats.instructions, 6);
+        assert_eq!(stats.heap_entries, 0);
+        assert_eq!(stats.heap_bytes, 0);
+        assert_eq!(stats.peak_heap_entries, 2);
+        assert!(stats.peak_heap_bytes > "a long string value".len());
+
+        vm.reset();
+        assert_eq!(vm.stats(), Stats::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile() -> Result<(),Error>  {
+        let mut vm = Vm::with_options(VmOptions { reflection: false, profile: true, ..Default::default() });
+        let mut labels = HashMap::new();
+        labels.insert("@func".to_string(), 2);
+        execute(&mut vm, vec![
+            ins(OpCode::Call, "@func"),
+            ins_e(OpCode::Hlt),
+            ins_e(OpCode::Nop),
+            ins_e(OpCode::Ret),
+        ], Some(labels))?;
+
+        assert_eq!(vm.profile().folded(), "main 2\nmain;func 2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_telemetry() {
+        struct Counter(Rc<RefCell<(u64, Vec<ErrorKind>)>>);
+        impl Telemetry for Counter {
+            fn finished(&mut self, stats: &Stats) {
+                self.0.borrow_mut().0 += stats.instructions;
+            }
+
+            fn failed(&mut self, error: &Error, _stats: &Stats) {
+                self.0.borrow_mut().1.push(error.kind);
+            }
+        }
+
+        let counts = Rc::new(RefCell::new((0, vec![])));
+        let mut vm = Vm::new(false);
+        vm.set_telemetry(Box::new(Counter(counts.clone())));
+        let result = execute(&mut vm, vec![ins_e(OpCode::Nop), ins_e(OpCode::Nop)], None);
+        assert!(result.is_ok());
+        let result = execute(&mut vm, vec![ins_e(OpCode::Pop)], None);
+        assert!(result.is_err());
+
+        assert_eq!(counts.borrow().0, 2);
+        assert_eq!(counts.borrow().1, vec![ErrorKind::StackUnderflow]);
+    }
+
+    #[test]
+    fn test_cancel_handle() {
+        let mut vm = Vm::new(false);
+        let handle = vm.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            handle.cancel();
+        });
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Jmp, 0),
+        ], None);
+        canceller.join().unwrap();
+
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Cancelled);
+        vm.reset();
+        assert!(!vm.cancel_handle().is_cancelled());
+    }
+
+    #[test]
+    fn test_sleep() -> Result<(),Error>  {
+        let start = Instant::now();
+        create_vm(vec![
+            ins(OpCode::Push, 20),
+            ins_e(OpCode::Sleep),
+        ], None)?;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let mut vm = Vm::new(false);
+        vm.cancel_handle().cancel();
+        let start = Instant::now();
+        let result = execute(&mut vm, vec![
+            ins(OpCode::Push, 60000),
+            ins_e(OpCode::Sleep),
+        ], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Cancelled);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heap_diff() -> Result<(),Error>  {
+        let mut vm = create_vm(vec![
+            ins(OpCode::Alloc, "$kept"),
+            ins(OpCode::Alloc, "$freed"),
+            ins_e(OpCode::HeapMark),
+            ins(OpCode::Alloc, "$leaked"),
+            ins(OpCode::Alloc, "$temporary"),
+            ins(OpCode::Free, "$temporary"),
+            ins(OpCode::Free, "$freed"),
+            ins_e(OpCode::HeapDiff),
+        ], None)?;
+
+        assert_eq!(vm.pop_stack()?.to_string(), "+$leaked -$freed");
+        assert_eq!(vm.heap_diff().unwrap(), HeapDiff { allocated: vec!["$leaked".to_string()], freed: vec!["$freed".to_string()] });
+
+        vm.heap_mark();
+        assert!(vm.heap_diff().unwrap().is_empty());
+
+        let result = create_vm(vec![ins_e(OpCode::HeapDiff)], None);
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Heap);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown() -> Result<(),Error>  {
+        let calls = Rc::new(RefCell::new(0));
+        let mut vm = create_vm(vec![
+            ins(OpCode::Alloc, "$a"),
+        ], None)?;
+        let counter = calls.clone();
+        vm.on_shutdown(move || *counter.borrow_mut() += 1);
+
+        vm.shutdown();
+        assert!(vm.heap.is_empty());
+        assert_eq!(vm.stats().heap_bytes, 0);
+        assert_eq!(*calls.borrow(), 1);
+
+        let counter = calls.clone();
+        vm.on_shutdown(move || *counter.borrow_mut() += 1);
+        drop(vm);
+        assert_eq!(*calls.borrow(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arena_end_without_begin() {
+        let result = create_vm(vec![
+            ins_e(OpCode::ArenaEnd),
+        ], None);
+
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Heap);
+    }
+
+    #[test]
+    fn test_linked_numeric_jump() -> Result<(),Error>  {
+        let mut program = Program::new();
+        program.instructions = vec![
+            ins(OpCode::Call, "@lib"),
+            ins_e(OpCode::Hlt),
+        ];
+        let mut lib = Program::new();
+        lib.instructions = vec![
+            ins(OpCode::Jmp, 2),
+            ins(OpCode::Push, "skipped"),
+            ins(OpCode::Push, "landed"),
+            ins_e(OpCode::Ret),
+        ];
+        lib.labels.insert("@lib".to_string(), 0);
+        program.link(lib).unwrap();
+
+        let mut vm = Vm::new(false);
+        vm.execute(program)?;
+        assert_eq!(vm.stack.len(), 1);
+        assert_eq!(vm.pop_stack()?.to_string(), "landed");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_runs() -> Result<(),Error> {
+        let mut before = Program::new();
+        before.instructions = vec![
+            ins(OpCode::Alloc, "$result"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Add),
+            ins(OpCode::Store, "$result"),
+        ];
+        let mut after = Program::new();
+        after.instructions = vec![
+            ins(OpCode::Alloc, "$result"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Mul),
+            ins(OpCode::Store, "$result"),
+        ];
+        assert_eq!(Vm::diff_runs(before.clone(), after, VmOptions::default())?, None);
+
+        let mut broken = Program::new();
+        broken.instructions = vec![
+            ins(OpCode::Alloc, "$result"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 3),
+            ins_e(OpCode::Add),
+            ins(OpCode::Store, "$result"),
+        ];
+        assert_eq!(Vm::diff_runs(before.clone(), broken, VmOptions::default())?,
+            Some("pc 2 (push 2) / pc 2 (push 3): stack[1] differs: I(2) != I(3)".to_string()));
+
+        let mut short = Program::new();
+        short.instructions = vec![
+            ins(OpCode::Alloc, "$result"),
+            ins(OpCode::Push, 2),
+            ins(OpCode::Push, 2),
+            ins_e(OpCode::Hlt),
+        ];
+        assert_eq!(Vm::diff_runs(before, short, VmOptions::default())?, Some("pc 3 (add) / pc 3 (hlt): stack[0] differs: I(4) != I(2)".to_string()));
+        Ok(())
+    }
+
     fn ins<T>(opcode: OpCode, item: T) -> Instruction where Field: From<T> {
         Instruction::new(opcode, vec![Field::from(item)])
     }
@@ -939,8 +3951,8 @@ mod test {
         let mut program = Program::new();
         program.instructions = instructions;
 
-        if labels.is_some() {
-            program.labels = labels.unwrap();
+        if let Some(labels) = labels {
+            program.labels = labels;
         }
 
         vm.execute(program)?;